@@ -0,0 +1,34 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::collections::BTreeMap;
+use std::path::Path;
+use workstyle::compute_workspace_names;
+use workstyle::config::parse_toml;
+use workstyle::window_manager::Window;
+
+/// Ten workspaces of ten windows each, matched against the default config
+fn naming(c: &mut Criterion) {
+    let config = parse_toml(
+        include_str!("../default_config.toml"),
+        Path::new("config.toml"),
+    )
+    .unwrap();
+    let apps = ["firefox", "kitty", "code", "thunderbird", "mpv", "unknown"];
+    let workspaces: BTreeMap<_, _> = (1..=10)
+        .map(|num| {
+            let windows = (0..10)
+                .map(|i| Window {
+                    name: Some(format!("Window {i}")),
+                    app_id: Some(apps[i % apps.len()].to_string()),
+                    ..Default::default()
+                })
+                .collect();
+            (num.to_string(), windows)
+        })
+        .collect();
+    c.bench_function("compute_workspace_names", |b| {
+        b.iter(|| compute_workspace_names(&config, &workspaces))
+    });
+}
+
+criterion_group!(benches, naming);
+criterion_main!(benches);
@@ -0,0 +1,112 @@
+// Companion extension of workstyle for GNOME Shell. Mutter only lets the shell name workspaces,
+// so this exposes the workspaces and the windows on them to workstyle over D-Bus, on the
+// `org.gnome.Shell` service at `/org/workstyle/Gnome`, and names the workspaces for it:
+// - `GetState() -> s` returns the workspaces, the active one and the windows as JSON
+// - `SetName(u index, s name)` names the workspace at this index
+// - `Changed` is emitted whenever the state may have changed
+
+import Gio from "gi://Gio";
+import Meta from "gi://Meta";
+import {Extension} from "resource:///org/gnome/shell/extensions/extension.js";
+
+const INTERFACE = `
+<node>
+  <interface name="org.workstyle.Gnome">
+    <method name="GetState">
+      <arg type="s" direction="out" name="state"/>
+    </method>
+    <method name="SetName">
+      <arg type="u" direction="in" name="index"/>
+      <arg type="s" direction="in" name="name"/>
+    </method>
+    <signal name="Changed"/>
+  </interface>
+</node>`;
+
+export default class WorkstyleExtension extends Extension {
+    enable() {
+        this._dbus = Gio.DBusExportedObject.wrapJSObject(INTERFACE, this);
+        this._dbus.export(Gio.DBus.session, "/org/workstyle/Gnome");
+        this._handlers = [];
+        this._connect(global.display, "window-created", (_display, window) => {
+            this._watch(window);
+            this._changed();
+        });
+        this._connect(global.display, "notify::focus-window", () => this._changed());
+        this._connect(global.display, "window-demands-attention", () => this._changed());
+        this._connect(global.display, "in-fullscreen-changed", () => this._changed());
+        this._connect(global.workspace_manager, "workspace-added", () => this._changed());
+        this._connect(global.workspace_manager, "workspace-removed", () => this._changed());
+        this._connect(global.workspace_manager, "active-workspace-changed", () => this._changed());
+        global.get_window_actors().forEach((actor) => this._watch(actor.meta_window));
+    }
+
+    disable() {
+        this._handlers.forEach(([object, id]) => object.disconnect(id));
+        this._handlers = [];
+        this._dbus.unexport();
+        this._dbus = null;
+    }
+
+    _connect(object, signal, callback) {
+        this._handlers.push([object, object.connect(signal, callback)]);
+    }
+
+    _watch(window) {
+        const ids = ["notify::title", "workspace-changed", "notify::urgent"].map((signal) =>
+            window.connect(signal, () => this._changed()),
+        );
+        const handlers = ids.map((id) => [window, id]);
+        this._handlers.push(...handlers);
+        const unmanaged = window.connect("unmanaged", () => {
+            handlers.concat([[window, unmanaged]]).forEach(([object, id]) => {
+                object.disconnect(id);
+                this._handlers = this._handlers.filter(([, handler]) => handler !== id);
+            });
+            this._changed();
+        });
+        this._handlers.push([window, unmanaged]);
+    }
+
+    _changed() {
+        this._dbus?.emit_signal("Changed", null);
+    }
+
+    GetState() {
+        const manager = global.workspace_manager;
+        const focused = global.display.focus_window;
+        const workspaces = [];
+        for (let index = 0; index < manager.get_n_workspaces(); index++)
+            workspaces.push({index, name: Meta.prefs_get_workspace_name(index)});
+        const windows = global
+            .get_window_actors()
+            .map((actor) => actor.meta_window)
+            .filter((window) => window.get_window_type() === Meta.WindowType.NORMAL)
+            .map((window) => {
+                const frame = window.get_frame_rect();
+                return {
+                    id: window.get_id(),
+                    title: window.get_title() ?? "",
+                    app_id: window.get_gtk_application_id() ?? window.get_sandboxed_app_id() ?? "",
+                    wm_class: window.get_wm_class() ?? "",
+                    wm_class_instance: window.get_wm_class_instance() ?? "",
+                    workspace: window.get_workspace()?.index() ?? null,
+                    on_all_workspaces: window.is_on_all_workspaces(),
+                    urgent: window.demands_attention || window.urgent,
+                    focused: window === focused,
+                    fullscreen: window.is_fullscreen(),
+                    x: frame.x,
+                    y: frame.y,
+                };
+            });
+        return JSON.stringify({
+            workspaces,
+            active: manager.get_active_workspace_index(),
+            windows,
+        });
+    }
+
+    SetName(index, name) {
+        Meta.prefs_change_workspace_name(index, name);
+    }
+}
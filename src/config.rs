@@ -1,44 +1,746 @@
-use anyhow::{Context, Result};
-use indexmap::map::IndexMap;
+use crate::window_manager::{pattern_matches, MatchOptions, Window};
+use crate::EnforceWindowManager;
+use anyhow::{anyhow, bail, Context, Result};
+use once_cell::sync::{Lazy, OnceCell};
 use serde::de::{self, Deserialize, Deserializer, Error};
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::{create_dir, File};
+use std::hash::{Hash, Hasher};
 use std::io::{BufReader, Read, Write};
-use std::path::PathBuf;
+use std::iter::once;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+use unicode_segmentation::UnicodeSegmentation;
 
 const DEFAULT_FALLBACK_ICON: &str = "-";
-const DEFAULT_SEPARATOR: &str = ": ";
+pub const DEFAULT_SEPARATOR: &str = ": ";
+const DEFAULT_FUZZY_THRESHOLD: f64 = 0.9;
+const DEFAULT_OVERFLOW_ICON: &str = "…";
 const DEFAULT_CONFIG: &str = include_str!("../default_config.toml");
 
+/// The config file given with `--config`, instead of the one from the config directory
+static PATH_OVERRIDE: OnceCell<PathBuf> = OnceCell::new();
+
+/// The config read from stdin with `--config -`, kept as stdin can only be read once
+static STDIN_CONFIG: OnceCell<String> = OnceCell::new();
+
+/// Use this config file instead of the one from the config directory. `-` stands for stdin,
+/// which is read right away
+pub fn override_path(path: PathBuf) -> Result<()> {
+    if path == Path::new("-") {
+        let mut contents = String::new();
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .context("Failed to read the configuration from stdin")?;
+        let _ = STDIN_CONFIG.set(contents);
+    }
+    let _ = PATH_OVERRIDE.set(path);
+    Ok(())
+}
+
+/// The config read from stdin, with `--config -`
+pub fn stdin_config() -> Option<&'static str> {
+    STDIN_CONFIG.get().map(String::as_str)
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Config {
-    pub mappings: IndexMap<String, String>,
+    /// Rules in order of precedence
+    pub rules: Vec<Rule>,
     pub other: Other,
+    /// Overrides for specific workspaces, by workspace number
+    pub workspaces: BTreeMap<String, WorkspaceOverrides>,
+    /// Files whose rules are merged into this config, relative to its directory
+    pub include: Vec<String>,
+    /// Base config this one is applied on top of, relative to its directory
+    pub extends: Option<String>,
+    /// Alternative sets of rules, by theme name
+    pub themes: BTreeMap<String, Vec<Rule>>,
+    /// `[other]` settings for a single window manager, from the `[sway]` and `[i3]` tables
+    pub backends: BTreeMap<String, toml::value::Table>,
+    /// The validated separator, computed once per load by `validate_separators`, or on first use
+    separator: OnceCell<String>,
+    /// The validated separators of the workspaces with overrides, by `[workspace.<number>]` key
+    workspace_separators: BTreeMap<String, String>,
+    /// A hash of the contents of the files this config was read from, to tell when they change
+    source_hash: u64,
+}
+
+/// Associates an icon to all windows matching any of its patterns
+///
+/// Can be written either as a simple mapping:
+/// "pattern" = "icon"
+/// or as a table:
+/// [[rule]]
+/// patterns = ["pattern", "other pattern"]
+/// icon = "icon"
+/// unless = "excluded pattern"
+/// priority = 1
+/// max = 3
+/// if_count = [{ at_least = 2, icon = "icons" }]
+/// focused_icon = "focused icon"
+/// note = "why this rule exists"
+#[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub patterns: Vec<String>,
+    pub icon: String,
+    /// The rule doesn't apply to windows matching this pattern, even if they match one of the
+    /// patterns
+    pub unless: Option<String>,
+    /// Rules with a higher priority take precedence. Rules with the same priority (by default 0)
+    /// are considered in order of apparition in the config file
+    #[serde(default)]
+    pub priority: i64,
+    /// Show the icon at most this many times per workspace, however many windows match the rule
+    pub max: Option<usize>,
+    /// Only match the patterns against this field of the windows
+    pub field: Option<WindowField>,
+    /// Only match windows that are, or aren't, fullscreen
+    pub fullscreen: Option<bool>,
+    /// Only match windows that are, or aren't, floating
+    pub floating: Option<bool>,
+    /// Alternative icons, for when many windows of a workspace match the rule
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub if_count: Vec<CountIcon>,
+    /// Used instead of the icon for the window that has the focus
+    pub focused_icon: Option<String>,
+    /// Free form annotation, which has no effect but is kept when the config is rewritten
+    #[serde(alias = "comment")]
+    pub note: Option<String>,
+}
+
+/// Icon used instead of the icon of a rule when the rule matches at least this many windows
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CountIcon {
+    pub at_least: usize,
+    pub icon: String,
+}
+
+impl Rule {
+    /// All the icons this rule may show
+    pub fn icons(&self) -> impl Iterator<Item = &String> {
+        once(&self.icon)
+            .chain(self.if_count.iter().map(|count| &count.icon))
+            .chain(self.focused_icon.iter())
+    }
+    /// The first pattern of this rule matching the window, if the rule applies to it
+    pub fn matching_pattern(&self, window: &Window, options: MatchOptions) -> Option<&str> {
+        if !self.applies_to(window, options) {
+            return None;
+        }
+        // A rule restricted to a field doesn't match when only another field is consulted
+        let field = match (self.field, options.field) {
+            (Some(field), Some(other)) if field != other => return None,
+            (field, other) => field.or(other),
+        };
+        let options = MatchOptions { field, ..options };
+        self.patterns
+            .iter()
+            .find(|pattern| window.matches(pattern, options))
+            .map(String::as_str)
+    }
+    /// Whether a pattern of this rule is exactly the app id of the window, if the rule applies
+    /// to it
+    pub fn matches_app_id(&self, window: &Window, options: MatchOptions) -> bool {
+        match &window.app_id {
+            Some(app_id)
+                if self.field.map_or(true, |field| field == WindowField::AppId)
+                    && self.applies_to(window, options) =>
+            {
+                self.patterns.iter().any(|pattern| {
+                    let pattern = pattern.trim_start_matches('^').trim_end_matches('$');
+                    pattern_matches(
+                        &format!("^{pattern}$"),
+                        app_id,
+                        options.normalize_separators,
+                    )
+                })
+            }
+            _ => false,
+        }
+    }
+    /// The icon to use when this rule matches this many windows of a workspace, if it isn't
+    /// the icon of the rule. The highest threshold reached wins
+    pub fn icon_for_count(&self, count: usize) -> Option<&str> {
+        self.if_count
+            .iter()
+            .filter(|count_icon| count >= count_icon.at_least)
+            .max_by_key(|count_icon| count_icon.at_least)
+            .map(|count_icon| count_icon.icon.as_str())
+    }
+    /// Whether the window is in the state this rule asks for, and isn't excluded by `unless`
+    fn applies_to(&self, window: &Window, options: MatchOptions) -> bool {
+        self.fullscreen
+            .map_or(true, |fullscreen| fullscreen == window.fullscreen)
+            && self
+                .floating
+                .map_or(true, |floating| floating == window.floating)
+            && !self.excludes(window, options)
+    }
+    /// Whether the `unless` pattern of this rule matches the window
+    pub fn excludes(&self, window: &Window, options: MatchOptions) -> bool {
+        self.unless
+            .as_ref()
+            .map(|unless| window.matches(unless, options))
+            .unwrap_or(false)
+    }
+    /// The pattern and icon of this rule, if it can be written as a simple mapping
+    fn as_mapping(&self) -> Option<(&str, &str)> {
+        let mapping = Rule {
+            patterns: self.patterns.clone(),
+            icon: self.icon.clone(),
+            ..Default::default()
+        };
+        match self.patterns.as_slice() {
+            [pattern]
+                if *self == mapping
+                    && ![
+                        "extends",
+                        "include",
+                        "other",
+                        "rule",
+                        "theme",
+                        "workspace",
+                        "sway",
+                        "i3",
+                    ]
+                    .contains(&pattern.as_str()) =>
+            {
+                Some((pattern.as_str(), self.icon.as_str()))
+            }
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Default, Clone)]
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
 #[serde(default, deny_unknown_fields)]
 pub struct Other {
     pub fallback_icon: Option<String>,
+    /// Icon of the windows that match no rule. Defaults to the fallback icon
+    pub unknown_icon: Option<String>,
+    /// Icon of the unknown windows with an app id or class but no title. Defaults to the unknown
+    /// icon
+    pub untitled_icon: Option<String>,
+    /// Icon of the unknown windows with a title but no app id or class. Defaults to the unknown
+    /// icon
+    pub no_app_icon: Option<String>,
+    /// Icon of the unknown windows with neither a title nor an app id or class. Defaults to the
+    /// unknown icon
+    pub blank_icon: Option<String>,
     pub separator: Option<String>,
     pub deduplicate_icons: bool,
+    /// What makes two icons duplicates of each other when `deduplicate_icons` is set
+    pub dedup_by: DedupBy,
+    /// How deduplicated icons show the number of windows they stand for
+    pub dedup_count_style: DedupCountStyle,
+    pub icon_order: IconOrder,
+    /// Collapse the runs of identical adjacent icons into the icon and the length of the run
+    pub compact_runs: bool,
+    /// Show the icons of all the rules a window matches, instead of only the first one
+    pub compose_matches: bool,
+    /// When no rule matches, fall back to the rule with the most similar pattern
+    pub fuzzy: bool,
+    /// Similarity score between 0 and 1 above which a fuzzy match is accepted
+    pub fuzzy_threshold: Option<f64>,
+    /// Maximum number of columns taken by the icons of a workspace
+    pub max_width: Option<usize>,
+    /// Replaces the icons that don't fit within `max_width`
+    pub overflow_icon: Option<String>,
+    /// Maximum number of grapheme clusters in a whole workspace name. Its icons are cut to fit,
+    /// ending with the `overflow_icon`, but the number is kept whole
+    pub max_name_len: Option<usize>,
+    /// Shell command run whenever a workspace is renamed
+    pub on_rename: Option<String>,
+    /// Report the windows in the sway/i3 scratchpad as a workspace of their own (only visible in
+    /// the JSON output, as the scratchpad can't be renamed)
+    pub show_scratchpad: bool,
+    /// Whether floating windows contribute icons. Defaults to true
+    pub include_floating: Option<bool>,
+    /// Log the windows that match no rule, once per window. Defaults to true
+    pub log_unknown: Option<bool>,
+    /// Shown before the icons of workspaces containing a window asking for attention
+    pub urgent_icon: Option<String>,
+    /// Shown as the icon of the focused workspace when it is empty
+    pub focused_empty_icon: Option<String>,
+    /// Keep the label the user gave to a workspace, like `code` in `3: code`, before the icons
+    pub keep_label: bool,
+    /// Shown as the icon of empty workspaces, where `{num}` stands for the workspace number
+    pub empty_icon: Option<String>,
+    /// Spaces added around specific icons, e.g. glyphs that overflow onto the next icon
+    pub icon_padding: BTreeMap<String, Padding>,
+    /// Shown after the icons of workspaces containing a fullscreen window
+    pub fullscreen_icon: Option<String>,
+    /// Template for the icon of the focused window, where `{icon}` is replaced by the icon
+    pub focused_wrap: Option<String>,
+    /// Icons contain pango markup, which is passed through untouched
+    pub pango: bool,
+    /// Once a window matched a rule, keep its icon until it closes, even if it stops matching
+    pub sticky_icons: bool,
+    /// Left pad workspace numbers with zeros to this width
+    pub number_width: Option<usize>,
+    /// Send a desktop notification about windows that keep being shown with the fallback icon
+    pub notify_unknown: bool,
+    /// Refresh the workspace names at least this often, even without any event. 0 means never
+    pub refresh_interval_secs: Option<u64>,
+    /// Fields consulted one at a time, in this order, before all of them, so the first one
+    /// matching a rule determines the icon
+    pub match_priority: Vec<WindowField>,
+    /// Remember the names of the workspaces across restarts, restoring them on startup if the
+    /// workspaces still hold the same windows
+    pub persist_names: bool,
+    /// Rename workspaces at most this often, even if events keep coming. 0 means no limit
+    pub min_rename_interval_ms: Option<u64>,
+    /// Name of the `[theme.<name>]` whose rules are used before the global ones
+    pub active_theme: Option<String>,
+    /// Unix socket streaming the names of the workspaces, as line delimited json
+    pub status_socket: Option<PathBuf>,
+    /// Consider `-` and `_` as the same character when matching patterns
+    pub normalize_separators: bool,
+    /// Also match patterns against all the fields of a window joined by spaces, e.g.
+    /// `firefox youtube`
+    pub match_combined: bool,
+    /// Patterns of the names of workspaces to leave alone
+    pub ignore_workspaces: Vec<String>,
+    /// Names of the outputs whose workspaces are left alone (sway, i3 and Hyprland)
+    pub ignore_outputs: Vec<String>,
+    /// Log how many windows matched a rule every this many passes, and on SIGUSR2. 0 means
+    /// only on SIGUSR2
+    pub match_stats_every: Option<usize>,
+    /// Append the logs to this file instead of printing them on stderr
+    pub log_file: Option<PathBuf>,
+    /// One of error, warn, info, debug or trace. RUST_LOG takes precedence
+    pub log_level: Option<String>,
+    /// Fail to load a configuration without any mapping, instead of only warning about it
+    pub require_mappings: bool,
+    /// Warn about icons that no installed font can render, using fontconfig
+    pub check_fonts: bool,
+    /// Kinds of Hyprland events after which the workspaces are renamed. Defaults to window
+    /// events, along with workspace events when the empty workspaces get an icon
+    pub hyprland_events: Option<Vec<HyprlandEvent>>,
+    /// After events about a single window, only rename the workspace containing it
+    pub partial_updates: bool,
+    /// Order in which to look for the window managers, when it isn't given on the command line
+    pub wm_priority: Option<Vec<EnforceWindowManager>>,
+}
+
+/// Number of spaces added on each side of an icon
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Padding {
+    pub left: usize,
+    pub right: usize,
+}
+
+/// Settings of a `[workspace.<number>]` section, taking precedence over the ones from `[other]`
+/// for that workspace
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceOverrides {
+    pub separator: Option<String>,
+    pub fallback_icon: Option<String>,
+    /// Fixed label shown before the icons of the workspace
+    pub prefix: Option<String>,
+    pub empty_icon: Option<String>,
+}
+
+impl WorkspaceOverrides {
+    fn apply(&self, other: &mut Other) {
+        if let Some(separator) = &self.separator {
+            other.separator = Some(separator.clone());
+        }
+        if let Some(fallback_icon) = &self.fallback_icon {
+            other.fallback_icon = Some(fallback_icon.clone());
+        }
+        if let Some(empty_icon) = &self.empty_icon {
+            other.empty_icon = Some(empty_icon.clone());
+        }
+    }
+}
+
+/// Order in which the icons of a workspace are displayed
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IconOrder {
+    /// Order of the windows on screen (or in the tree if the WM doesn't expose positions)
+    #[default]
+    Window,
+    /// Order of appearance of the matching patterns in the config file
+    Config,
+    /// Alphabetical order of the icons themselves
+    Alpha,
+}
+
+/// What `deduplicate_icons` considers as duplicates
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupBy {
+    /// Windows with the same icon
+    #[default]
+    Icon,
+    /// Windows matching the same rule, so different apps sharing an icon are all shown
+    App,
+}
+
+/// How deduplicated icons show the number of windows they stand for
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupCountStyle {
+    /// They don't
+    #[default]
+    None,
+    /// As a superscript after the icon, e.g. `🦊²`
+    Superscript,
+    /// Before the icon, e.g. `2×🦊`
+    Prefix,
+}
+
+/// Fields identifying a window
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowField {
+    /// The title
+    Name,
+    /// The Wayland app id
+    AppId,
+    /// The class of the X11 WM_CLASS
+    #[serde(alias = "window_properties_class")]
+    Class,
+    /// The instance of the X11 WM_CLASS
+    #[serde(alias = "window_properties_instance")]
+    Instance,
+    /// The sway/i3 marks. Unlike the other fields, they're only matched by the rules restricted to
+    /// them
+    Mark,
+}
+
+/// Kinds of Hyprland events
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HyprlandEvent {
+    /// A window was opened, closed or moved
+    Window,
+    /// A layer surface, like a bar or a notification, was opened or closed
+    Layer,
+    /// The active workspace changed
+    Workspace,
 }
 
 impl Config {
     pub fn new() -> Result<Self> {
         let path = Self::path()?;
-        if path.exists() {
-            let mut buf = String::new();
-            File::open(path)
-                .and_then(|f| BufReader::new(f).read_to_string(&mut buf))
-                .context("Failed to read configuration file")?;
-            Ok(toml::from_str(&buf)?)
+        let config = match Self::read(&path)? {
+            Some(config) => config,
+            None if PATH_OVERRIDE.get().is_some() => {
+                bail!("Configuration file: {path:?} doesn't exist")
+            }
+            None => {
+                File::create(&path)
+                    .and_then(|mut f| f.write_all(DEFAULT_CONFIG.as_bytes()))
+                    .context("Failed to create default configuration file")?;
+                let mut config: Config = toml::from_str(DEFAULT_CONFIG)?;
+                config.source_hash = hash_source(DEFAULT_CONFIG, 0);
+                config
+            }
+        };
+        Self::finish(config, &path)
+    }
+
+    /// Like `new`, without writing the default configuration when there is none. None then
+    pub fn load() -> Result<Option<Self>> {
+        let path = Self::path()?;
+        Self::read(&path)?
+            .map(|config| Self::finish(config, &path))
+            .transpose()
+    }
+
+    /// The configuration as written in the file at this path, or in its legacy YAML version, if
+    /// there is one
+    fn read(path: &Path) -> Result<Option<Self>> {
+        let config = if let Some(contents) = stdin_config() {
+            // Bases and includes are relative to the current directory
+            parse_with_base(contents, path, 0)?.0
+        } else if path.exists() {
+            let contents = read_file(path)?;
+            // Errors are returned as is: the config file is only ever written when it is missing
+            if contents.trim().is_empty() {
+                bail!("Configuration file: {path:?} is empty. Remove it to get the default configuration back");
+            }
+            parse_with_base(&contents, path, 0)?.0
+        } else if let Some(yaml_path) = Self::yaml_path(path) {
+            static WARN_ONCE: Once = Once::new();
+            WARN_ONCE.call_once(|| {
+                warn!("Using legacy configuration file: {yaml_path:?}. Consider migrating it to: {path:?}");
+            });
+            Self::from_yaml_file(&yaml_path)?
         } else {
-            File::create(path)
-                .and_then(|mut f| f.write_all(DEFAULT_CONFIG.as_bytes()))
-                .context("Failed to create default configuration file")?;
-            Ok(toml::from_str(DEFAULT_CONFIG)?)
+            return Ok(None);
+        };
+        Ok(Some(config))
+    }
+
+    /// Expand and check the configuration read from the file at this path
+    fn finish(mut config: Self, path: &Path) -> Result<Self> {
+        config.expand_env_vars()?;
+        if let Some(dir) = path.parent() {
+            config.merge_includes(dir)?;
+        }
+        // With `require_mappings`, we refuse to start instead, see `check_mappings`
+        if !config.has_mappings() && !config.other.require_mappings {
+            static WARN_ONCE: Once = Once::new();
+            WARN_ONCE.call_once(|| {
+                warn!("Configuration file: {path:?} has no mappings, so all windows will get the fallback icon");
+            });
+        }
+        config.validate_separators();
+        Ok(config)
+    }
+
+    /// Check the separators once, for this config and for each workspace overriding it, so the
+    /// per workspace views of the config share the result instead of checking them again
+    pub(crate) fn validate_separators(&mut self) {
+        self.separator = OnceCell::new();
+        let _ = self.separator.set(self.validated_separator().to_string());
+        // The overrides of the icons matter as much as the ones of the separator
+        let overridden: Vec<_> = self
+            .workspaces
+            .iter()
+            .map(|(key, overrides)| {
+                let mut other = self.other.clone();
+                overrides.apply(&mut other);
+                (key.clone(), other)
+            })
+            .collect();
+        self.workspace_separators = BTreeMap::new();
+        for (key, other) in overridden {
+            let other = std::mem::replace(&mut self.other, other);
+            let separator = self.validated_separator().to_string();
+            self.other = other;
+            self.workspace_separators.insert(key, separator);
+        }
+    }
+
+    pub fn source_hash(&self) -> u64 {
+        self.source_hash
+    }
+
+    pub fn has_mappings(&self) -> bool {
+        !self.rules.is_empty() || !self.themes.is_empty()
+    }
+
+    /// Fail if the config has no mappings but requires some. This is only checked on startup, as
+    /// failing to load the config later on would only make us retry forever
+    pub fn check_mappings(&self) -> Result<()> {
+        if self.other.require_mappings && !self.has_mappings() {
+            bail!(
+                "Configuration file: {:?} has no mappings, but `require_mappings` is set",
+                Self::path()?
+            );
+        }
+        Ok(())
+    }
+
+    /// Put the rules of the active theme in front of the global ones, which are used for the
+    /// windows it has no icon for. `theme` takes precedence over `active_theme` from `[other]`
+    pub fn apply_theme(&mut self, theme: Option<&str>) -> Result<()> {
+        let name = match theme.or(self.other.active_theme.as_deref()) {
+            Some(name) => name.to_string(),
+            None => return Ok(()),
+        };
+        let mut rules = self
+            .themes
+            .get(&name)
+            .with_context(|| format!("Unknown theme: {name}"))?
+            .clone();
+        rules.append(&mut self.rules);
+        // Stable sort: ties go to the theme
+        rules.sort_by_key(|rule| Reverse(rule.priority));
+        self.rules = rules;
+        self.validate_separators();
+        Ok(())
+    }
+
+    /// Apply the `[sway]` or `[i3]` settings over the ones from `[other]`, depending on the
+    /// window manager we're connected to
+    pub fn apply_backend(&mut self, wm_name: &str) -> Result<()> {
+        let overrides = match self.backends.get(&wm_name.to_lowercase()) {
+            Some(overrides) => overrides.clone(),
+            None => return Ok(()),
+        };
+        let other = match toml::Value::try_from(&self.other)? {
+            toml::Value::Table(other) => other,
+            _ => bail!("Expected `other` to be a table"),
+        };
+        self.other = Other::deserialize(toml::Value::Table(merge_tables(overrides, other)))
+            .with_context(|| format!("Invalid `{}`", wm_name.to_lowercase()))?;
+        self.validate_separators();
+        Ok(())
+    }
+
+    /// Expand the environment variables in the icons and paths of this config
+    pub fn expand_env_vars(&mut self) -> Result<()> {
+        let other = &mut self.other;
+        let values = self
+            .rules
+            .iter_mut()
+            .chain(self.themes.values_mut().flatten())
+            .flat_map(|rule| {
+                once(&mut rule.icon)
+                    .chain(rule.if_count.iter_mut().map(|count| &mut count.icon))
+                    .chain(rule.focused_icon.iter_mut())
+            })
+            .chain(self.include.iter_mut())
+            .chain(
+                [
+                    &mut other.fallback_icon,
+                    &mut other.unknown_icon,
+                    &mut other.untitled_icon,
+                    &mut other.no_app_icon,
+                    &mut other.blank_icon,
+                    &mut other.overflow_icon,
+                    &mut other.urgent_icon,
+                    &mut other.fullscreen_icon,
+                    &mut other.focused_empty_icon,
+                    &mut other.empty_icon,
+                ]
+                .into_iter()
+                .flatten(),
+            )
+            .chain(self.workspaces.values_mut().flat_map(|overrides| {
+                [&mut overrides.fallback_icon, &mut overrides.prefix]
+                    .into_iter()
+                    .flatten()
+            }));
+        for value in values {
+            *value = expand_env(value)?;
+        }
+        for path in [&mut self.other.log_file, &mut self.other.status_socket]
+            .into_iter()
+            .flatten()
+        {
+            // Paths come from toml strings, so they're valid unicode
+            *path = expand_env(&path.to_string_lossy())?.into();
+        }
+        Ok(())
+    }
+
+    /// Merge the rules of the included files into this config. Later includes take precedence
+    /// over earlier ones, and the rules of this config over all of them
+    pub(crate) fn merge_includes(&mut self, dir: &Path) -> Result<()> {
+        // The rules of the includes merged so far, which are the later ones
+        let mut later_rules = Vec::new();
+        for include in self.include.iter().rev() {
+            let path = dir.join(include);
+            let contents = read_file(&path)?;
+            self.source_hash = hash_source(&contents, self.source_hash);
+            let mut included = parse_toml(&contents, &path)?;
+            included.expand_env_vars()?;
+            if !included.include.is_empty() {
+                warn!("Ignoring the includes of included file: {path:?}");
+            }
+            let ignored = ignored_in_include(&contents);
+            if !ignored.is_empty() {
+                warn!(
+                    "Only merging the rules of included file: {path:?}, ignoring its: {}",
+                    ignored.join(", ")
+                );
+            }
+            for (earlier, later) in duplicate_mappings(&included.rules, &later_rules) {
+                warn!(
+                    "Duplicate mapping: {:?} is mapped to: \"{}\" in included file: {path:?}, and to: \"{}\" in a later one, which wins",
+                    earlier.patterns, earlier.icon, later.icon
+                );
+            }
+            later_rules.extend(included.rules.iter().cloned());
+            add_rules(&mut self.rules, included.rules);
         }
+        Ok(())
+    }
+
+    fn from_yaml_file(path: &Path) -> Result<Self> {
+        Self::from_yaml(&read_file(path)?)
+    }
+
+    pub(crate) fn from_yaml(contents: &str) -> Result<Self> {
+        let mut config: Self =
+            serde_yaml::from_str(contents).context("Failed to parse config as yaml")?;
+        config.source_hash = hash_source(contents, 0);
+        Ok(config)
+    }
+
+    /// Convert the legacy yaml config file to a toml config file next to it.
+    /// Returns the path of the new config file with the number of converted rules
+    pub fn migrate() -> Result<(PathBuf, usize)> {
+        let path = Self::path()?;
+        let yaml_path =
+            Self::yaml_path(&path).context("Couldn't find a yaml configuration file to migrate")?;
+        if path.exists() {
+            bail!("Refusing to overwrite existing configuration file: {path:?}");
+        }
+        let config = Self::from_yaml_file(&yaml_path)?;
+        let toml = config.to_toml()?;
+        File::create(&path)
+            .and_then(|mut f| f.write_all(toml.as_bytes()))
+            .context("Failed to write migrated configuration file")?;
+        Ok((path, config.rules.len()))
+    }
+
+    /// Append a commented out mapping for this unknown window to the config file, so the user
+    /// only has to uncomment it and pick an icon
+    pub fn learn(window: &Window) -> Result<()> {
+        let path = Self::path()?;
+        if !path.exists() {
+            bail!("Can only learn windows into a toml configuration file: {path:?}");
+        }
+        if let Some(contents) = with_suggested_mapping(&read_file(&path)?, window) {
+            File::create(&path)
+                .and_then(|mut f| f.write_all(contents.as_bytes()))
+                .context("Failed to write configuration file")?;
+            info!("Added a suggested mapping for window: {window:?} to: {path:?}");
+        }
+        Ok(())
+    }
+
+    /// Serialize this config in the toml format
+    pub fn to_toml(&self) -> Result<String> {
+        let mut table = toml::value::Table::new();
+        if let Some(extends) = &self.extends {
+            table.insert("extends".to_string(), toml::Value::String(extends.clone()));
+        }
+        if !self.include.is_empty() {
+            table.insert("include".to_string(), toml::Value::try_from(&self.include)?);
+        }
+        insert_rules(&mut table, &self.rules)?;
+        let other = non_default_settings(&self.other)?;
+        if !other.is_empty() {
+            table.insert("other".to_string(), toml::Value::Table(other));
+        }
+        if !self.workspaces.is_empty() {
+            table.insert(
+                "workspace".to_string(),
+                toml::Value::try_from(&self.workspaces)?,
+            );
+        }
+        if !self.themes.is_empty() {
+            let mut themes = toml::value::Table::new();
+            for (name, rules) in &self.themes {
+                let mut theme = toml::value::Table::new();
+                insert_rules(&mut theme, rules)?;
+                themes.insert(name.clone(), toml::Value::Table(theme));
+            }
+            table.insert("theme".to_string(), toml::Value::Table(themes));
+        }
+        for (name, overrides) in &self.backends {
+            table.insert(name.clone(), toml::Value::Table(overrides.clone()));
+        }
+        toml::to_string(&toml::Value::Table(table)).context("Failed to serialize config as toml")
     }
 
     #[cfg(test)]
@@ -53,25 +755,262 @@ impl Config {
             .unwrap_or(DEFAULT_FALLBACK_ICON)
     }
 
-    pub fn separator(&self) -> &str {
-        let sep = self.other.separator.as_deref();
-        if let Some(sep) = sep {
-            let fallback_icon = self.fallback_icon();
-            if let Some(icon) = self.mappings.values().find(|icon| icon.contains(sep)) {
-                error!("Can't use separator: \"{sep}\" as it is contained in icon: \"{icon}\".");
-                DEFAULT_SEPARATOR
-            } else if fallback_icon.contains(sep) {
-                error!("Can't use separator: \"{sep}\" as it is contained in fallback icon: \"{fallback_icon}\"");
-                DEFAULT_SEPARATOR
-            } else {
-                sep
+    pub fn unknown_icon(&self) -> &str {
+        self.other
+            .unknown_icon
+            .as_deref()
+            .unwrap_or_else(|| self.fallback_icon())
+    }
+
+    /// The icon of a window that matches no rule, depending on which of its fields are known
+    pub fn unknown_icon_for(&self, window: &Window) -> &str {
+        let has_app = window.app_id.is_some()
+            || window.window_properties_class.is_some()
+            || window.window_properties_instance.is_some();
+        let icon = match (window.name.is_some(), has_app) {
+            (true, true) => None,
+            (false, true) => self.other.untitled_icon.as_deref(),
+            (true, false) => self.other.no_app_icon.as_deref(),
+            (false, false) => self.other.blank_icon.as_deref(),
+        };
+        icon.unwrap_or_else(|| self.unknown_icon())
+    }
+
+    /// All the icons of the windows that match no rule
+    pub fn unknown_icons(&self) -> impl Iterator<Item = &str> {
+        once(self.unknown_icon()).chain(
+            [
+                &self.other.untitled_icon,
+                &self.other.no_app_icon,
+                &self.other.blank_icon,
+            ]
+            .into_iter()
+            .flatten()
+            .map(String::as_str),
+        )
+    }
+
+    /// The overrides for the workspace with this number, if any
+    pub fn workspace_overrides(&self, num: &str) -> Option<&WorkspaceOverrides> {
+        self.workspace_entry(num).map(|(_, overrides)| overrides)
+    }
+
+    /// The `[workspace.<number>]` key and overrides for the workspace with this number, if any
+    fn workspace_entry(&self, num: &str) -> Option<(&String, &WorkspaceOverrides)> {
+        self.workspaces.iter().find(|(key, _)| {
+            match (key.parse::<u64>(), num.parse::<u64>()) {
+                // So padded numbers still find their overrides
+                (Ok(key), Ok(num)) => key == num,
+                _ => key.as_str() == num,
+            }
+        })
+    }
+
+    /// The config to use for the workspace with this number, where its overrides take precedence
+    /// over the settings from `[other]`
+    pub fn for_workspace(&self, num: &str) -> Cow<'_, Config> {
+        match self.workspace_entry(num) {
+            Some((key, overrides)) => {
+                let mut config = self.clone();
+                overrides.apply(&mut config.other);
+                config.separator = OnceCell::new();
+                if let Some(separator) = self.workspace_separators.get(key) {
+                    let _ = config.separator.set(separator.clone());
+                }
+                Cow::Owned(config)
+            }
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    pub fn overflow_icon(&self) -> &str {
+        self.other
+            .overflow_icon
+            .as_deref()
+            .unwrap_or(DEFAULT_OVERFLOW_ICON)
+    }
+
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.other
+            .refresh_interval_secs
+            .filter(|&secs| secs != 0)
+            .map(Duration::from_secs)
+    }
+
+    pub fn min_rename_interval(&self) -> Option<Duration> {
+        self.other
+            .min_rename_interval_ms
+            .filter(|&ms| ms != 0)
+            .map(Duration::from_millis)
+    }
+
+    pub fn match_options(&self) -> MatchOptions {
+        MatchOptions {
+            normalize_separators: self.other.normalize_separators,
+            combined_fields: self.other.match_combined,
+            field: None,
+        }
+    }
+
+    /// Whether the workspace with this name should be left alone
+    pub fn ignores_workspace(&self, name: &str) -> bool {
+        self.other
+            .ignore_workspaces
+            .iter()
+            .any(|pattern| pattern_matches(pattern, name, self.other.normalize_separators))
+    }
+
+    /// Whether the workspaces on the output with this name should be left alone
+    pub fn ignores_output(&self, name: &str) -> bool {
+        self.other
+            .ignore_outputs
+            .iter()
+            .any(|output| output == name)
+    }
+
+    pub fn hyprland_events(&self) -> &[HyprlandEvent] {
+        match self.other.hyprland_events.as_deref() {
+            Some(events) => events,
+            // The empty workspaces get their icons when they're focused, which only workspace
+            // events tell about
+            None if self.has_empty_icon() || self.other.focused_empty_icon.is_some() => {
+                &[HyprlandEvent::Window, HyprlandEvent::Workspace]
             }
+            None => &[HyprlandEvent::Window],
+        }
+    }
+
+    /// Whether any of the workspaces gets the `empty_icon`
+    pub fn has_empty_icon(&self) -> bool {
+        self.other.empty_icon.is_some()
+            || self
+                .workspaces
+                .values()
+                .any(|overrides| overrides.empty_icon.is_some())
+    }
+
+    pub fn include_floating(&self) -> bool {
+        self.other.include_floating.unwrap_or(true)
+    }
+
+    pub fn log_unknown(&self) -> bool {
+        self.other.log_unknown.unwrap_or(true)
+    }
+
+    pub fn fuzzy_threshold(&self) -> f64 {
+        self.other
+            .fuzzy_threshold
+            .unwrap_or(DEFAULT_FUZZY_THRESHOLD)
+    }
+
+    pub fn separator(&self) -> &str {
+        self.separator
+            .get_or_init(|| self.validated_separator().to_string())
+    }
+
+    /// The separator from `[other]`, or the default separator if the configured one is contained
+    /// in an icon
+    fn validated_separator(&self) -> &str {
+        match self.other.separator.as_deref() {
+            Some(sep) => match self.separator_conflict() {
+                Some(conflict) => {
+                    error_once(conflict);
+                    DEFAULT_SEPARATOR
+                }
+                None => sep,
+            },
+            None => DEFAULT_SEPARATOR,
+        }
+    }
+
+    /// Why the separator from `[other]` can't be used, if it is contained in an icon
+    pub fn separator_conflict(&self) -> Option<String> {
+        let sep = self.other.separator.as_deref()?;
+        if sep.is_empty() {
+            return self.empty_separator_conflict();
+        }
+        let contains_sep = |icon: &str| contains_graphemes(&self.displayed_text(icon), sep);
+        let fallback_icon = self.fallback_icon();
+        if let Some(icon) = self
+            .rules
+            .iter()
+            .flat_map(Rule::icons)
+            .find(|icon| contains_sep(icon))
+        {
+            Some(format!(
+                "Can't use separator: \"{sep}\" as it is contained in icon: \"{icon}\"."
+            ))
+        } else if contains_sep(fallback_icon) {
+            Some(format!("Can't use separator: \"{sep}\" as it is contained in fallback icon: \"{fallback_icon}\""))
         } else {
-            DEFAULT_SEPARATOR
+            self.unknown_icons()
+                .find(|icon| contains_sep(icon))
+                .map(|unknown_icon| format!("Can't use separator: \"{sep}\" as it is contained in unknown icon: \"{unknown_icon}\""))
         }
     }
 
+    /// Why the icons can't directly follow the workspace number, if the name could start with a
+    /// digit after the number, which would be read back as part of the number
+    fn empty_separator_conflict(&self) -> Option<String> {
+        if self.other.deduplicate_icons && self.other.dedup_count_style == DedupCountStyle::Prefix {
+            return Some("Can't use an empty separator with `dedup_count_style = \"prefix\"`, as the counts would be read back as part of the workspace number".to_string());
+        }
+        let starts_with_digit = |icon: &str| {
+            // Empty workspaces can be named after their number
+            icon.starts_with("{num}")
+                || self
+                    .displayed_text(icon)
+                    .starts_with(|c: char| c.is_ascii_digit())
+        };
+        let other = &self.other;
+        self.rules
+            .iter()
+            .flat_map(Rule::icons)
+            .map(String::as_str)
+            .chain(once(self.fallback_icon()))
+            .chain(self.unknown_icons())
+            .chain(
+                [
+                    &other.urgent_icon,
+                    &other.focused_empty_icon,
+                    &other.empty_icon,
+                    &other.focused_wrap,
+                ]
+                .into_iter()
+                .flatten()
+                .map(String::as_str),
+            )
+            .chain(self.workspaces.values().flat_map(|overrides| {
+                [&overrides.prefix, &overrides.empty_icon]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+            }))
+            .find(|icon| starts_with_digit(icon))
+            .map(|icon| format!("Can't use an empty separator as icon: \"{icon}\" starts with a digit, which would be read back as part of the workspace number"))
+    }
+
+    /// The text displayed for this icon, without markup when the config uses pango markup
+    pub fn displayed_text<'a>(&self, icon: &'a str) -> Cow<'a, str> {
+        if self.other.pango {
+            strip_markup(icon).into()
+        } else {
+            icon.into()
+        }
+    }
+
+    /// Path to a legacy yaml config file living next to the toml one, if there is one
+    pub fn yaml_path(toml_path: &Path) -> Option<PathBuf> {
+        ["yaml", "yml"]
+            .into_iter()
+            .map(|extension| toml_path.with_extension(extension))
+            .find(|path| path.exists())
+    }
+
     pub fn path() -> Result<PathBuf> {
+        if let Some(path) = PATH_OVERRIDE.get() {
+            return Ok(path.clone());
+        }
         let mut user_path = dirs::config_dir().context("Could not find the configuration path")?;
         let mut system_path = PathBuf::from("/etc/xdg");
 
@@ -94,6 +1033,299 @@ impl Config {
     }
 }
 
+/// The `[other]` settings that differ from their default, so a converted config file only has
+/// the settings the user chose
+fn non_default_settings(other: &Other) -> Result<toml::value::Table> {
+    let table = |other: &Other| match toml::Value::try_from(other)? {
+        toml::Value::Table(table) => Ok(table),
+        _ => bail!("Expected `other` to be a table"),
+    };
+    let defaults = table(&Other::default())?;
+    let mut settings = table(other)?;
+    settings.retain(|key, value| defaults.get(key) != Some(value));
+    Ok(settings)
+}
+
+/// Write these rules into the table, as simple mappings when possible
+fn insert_rules(table: &mut toml::value::Table, rules: &[Rule]) -> Result<()> {
+    let mut tables = Vec::new();
+    for rule in rules {
+        match rule.as_mapping() {
+            // Simple mappings are read before rule tables, so to preserve precedence, they can
+            // only be used until the first rule table
+            Some((pattern, icon)) if tables.is_empty() => {
+                table.insert(pattern.to_string(), toml::Value::String(icon.to_string()));
+            }
+            _ => tables.push(toml::Value::try_from(rule)?),
+        }
+    }
+    if !tables.is_empty() {
+        table.insert("rule".to_string(), toml::Value::Array(tables));
+    }
+    Ok(())
+}
+
+/// Log this error, unless it was already logged: as the config is read again on every event, the
+/// same problem would be reported over and over
+fn error_once(message: String) {
+    static REPORTED: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+    if REPORTED.lock().unwrap().insert(message.clone()) {
+        error!("{message}");
+    }
+}
+
+/// Remove pango markup tags and entities, leaving the displayed text
+fn strip_markup(markup: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in markup.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The config file contents with a commented out mapping for this window, or None if the
+/// config file already has one for it.
+/// The mapping goes before the first table, so it's still a simple mapping once uncommented
+pub(crate) fn with_suggested_mapping(contents: &str, window: &Window) -> Option<String> {
+    let key = window
+        .app_id
+        .as_deref()
+        .or(window.window_properties_class.as_deref())
+        .or(window.name.as_deref())?;
+    let key = toml::Value::String(key.to_string()).to_string();
+    let is_known = contents.lines().any(|line| {
+        line.trim_start_matches(|c: char| c == '#' || c.is_whitespace())
+            .strip_prefix(&key)
+            .map_or(false, |rest| rest.trim_start().starts_with('='))
+    });
+    if is_known {
+        return None;
+    }
+    let suggestion = format!("# {key} = \"{DEFAULT_FALLBACK_ICON}\"\n");
+    let mut lines = contents.split_inclusive('\n').collect::<Vec<_>>();
+    match lines
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+    {
+        Some(first_table) => lines.insert(first_table, &suggestion),
+        None => {
+            if !contents.is_empty() && !contents.ends_with('\n') {
+                lines.push("\n");
+            }
+            lines.push(&suggestion);
+        }
+    }
+    Some(lines.concat())
+}
+
+/// Replace `$VAR` and `${VAR}` with the value of the environment variable. `$$` is a literal `$`,
+/// as is a `$` that isn't followed by the name of a variable, like in `$5`
+pub(crate) fn expand_env(value: &str) -> Result<String> {
+    let is_name_char = |c: char| c == '_' || c.is_ascii_alphanumeric();
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let (name, after) = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .with_context(|| format!("Unterminated variable in config value: {value:?}"))?;
+            (&braced[..end], &braced[end + 1..])
+        } else if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+            continue;
+        } else {
+            let end = match rest.chars().next() {
+                // Names of variables don't start with a digit
+                Some(c) if c.is_ascii_digit() => 0,
+                _ => rest.find(|c| !is_name_char(c)).unwrap_or(rest.len()),
+            };
+            (&rest[..end], &rest[end..])
+        };
+        if name.is_empty() {
+            // Not a variable, e.g. a trailing `$`
+            expanded.push('$');
+            continue;
+        }
+        let var = std::env::var(name).with_context(|| {
+            format!("Environment variable: {name} used in config value: {value:?} is not set")
+        })?;
+        expanded.push_str(&var);
+        rest = after;
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Parse the contents of this toml config file, with an error pointing at the faulty line
+pub fn parse_toml(contents: &str, path: &Path) -> Result<Config> {
+    toml::from_str(contents).map_err(|e| {
+        let message = e.to_string();
+        // Unknown keys are reported without their position, so look for them in the file
+        let line = e.line_col().is_none().then(|| {
+            let key = message.split("unknown field `").nth(1)?.split('`').next()?;
+            contents.lines().position(|line| {
+                line.trim_start()
+                    .strip_prefix(key)
+                    .map_or(false, |rest| rest.trim_start().starts_with('='))
+            })
+        });
+        match line.flatten() {
+            Some(line) => anyhow!("{message} at line {}", line + 1),
+            None => anyhow!(message),
+        }
+        .context(format!("Failed to parse configuration file: {path:?}"))
+    })
+}
+
+/// The settings of an included file that aren't merged into the including config, as only its
+/// rules are
+pub(crate) fn ignored_in_include(contents: &str) -> Vec<&'static str> {
+    let table = match contents.parse::<toml::Value>() {
+        Ok(toml::Value::Table(table)) => table,
+        _ => return Vec::new(),
+    };
+    ["extends", "other", "workspace", "theme", "sway", "i3"]
+        .into_iter()
+        .filter(|key| table.contains_key(*key))
+        .collect()
+}
+
+/// Whether the text contains the pattern as whole grapheme clusters, so a separator isn't found
+/// inside a composed emoji, e.g. `1` inside the keycap `1️⃣`
+pub(crate) fn contains_graphemes(text: &str, pattern: &str) -> bool {
+    let text: Vec<_> = text.graphemes(true).collect();
+    let pattern: Vec<_> = pattern.graphemes(true).collect();
+    !pattern.is_empty() && text.windows(pattern.len()).any(|window| window == pattern)
+}
+
+/// How many bases deep a configuration can be, so cycles of `extends` are reported
+const MAX_EXTENDS_DEPTH: usize = 8;
+
+/// Parse a configuration file on top of the base configuration it `extends`, if any.
+/// Also returns its `[other]` and `[workspace]` tables merged over the ones of its base, as
+/// these settings are merged one by one
+pub(crate) fn parse_with_base(
+    contents: &str,
+    path: &Path,
+    depth: usize,
+) -> Result<(Config, toml::value::Table)> {
+    let mut config = parse_toml(contents, path)?;
+    config.source_hash = hash_source(contents, 0);
+    let mut settings: toml::value::Table = toml::from_str::<toml::value::Table>(contents)?
+        .into_iter()
+        .filter(|(key, _)| key == "other" || key == "workspace")
+        .collect();
+    if let Some(base) = &config.extends {
+        if depth >= MAX_EXTENDS_DEPTH {
+            bail!(
+                "Configuration file: {path:?} extends too many bases. Do they extend each other?"
+            );
+        }
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let base_path = dir.join(expand_env(base)?);
+        let (base, base_settings) = parse_with_base(&read_file(&base_path)?, &base_path, depth + 1)
+            .with_context(|| format!("Failed to load base configuration file: {base_path:?}"))?;
+        if !base.include.is_empty() {
+            warn!("Ignoring the includes of base file: {base_path:?}");
+        }
+        settings = merge_tables(settings, base_settings);
+        let table = |key: &str| {
+            settings
+                .get(key)
+                .cloned()
+                .unwrap_or_else(|| toml::Value::Table(toml::value::Table::new()))
+        };
+        config.other = Other::deserialize(table("other"))
+            .context("Invalid `other` once merged with the base configuration")?;
+        config.workspaces = BTreeMap::<String, WorkspaceOverrides>::deserialize(table("workspace"))
+            .context("Invalid `workspace` once merged with the base configuration")?;
+        config.source_hash = hash_source(contents, base.source_hash);
+        add_rules(&mut config.rules, base.rules);
+        for (name, rules) in base.themes {
+            add_rules(config.themes.entry(name).or_default(), rules);
+        }
+        for (name, base_overrides) in base.backends {
+            let overrides = config.backends.remove(&name).unwrap_or_default();
+            config
+                .backends
+                .insert(name, merge_tables(overrides, base_overrides));
+        }
+    }
+    Ok((config, settings))
+}
+
+/// A hash of the contents of a config file, along with the hash of the files it is based on
+fn hash_source(contents: &str, base: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (contents, base).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The values of `table` over the ones of `base`, merging the tables they both have
+fn merge_tables(mut table: toml::value::Table, base: toml::value::Table) -> toml::value::Table {
+    for (key, base_value) in base {
+        match (table.get_mut(&key), base_value) {
+            (Some(toml::Value::Table(value)), toml::Value::Table(base_value)) => {
+                *value = merge_tables(std::mem::take(value), base_value);
+            }
+            (Some(_), _) => {}
+            (None, base_value) => {
+                table.insert(key, base_value);
+            }
+        }
+    }
+    table
+}
+
+/// Add the rules whose patterns aren't known yet, after the known ones
+fn add_rules(known: &mut Vec<Rule>, rules: Vec<Rule>) {
+    for rule in rules {
+        if !known.iter().any(|known| known.patterns == rule.patterns) {
+            known.push(rule);
+        }
+    }
+    // Stable sort: ties are kept in order of precedence
+    known.sort_by_key(|rule| Reverse(rule.priority));
+}
+
+/// The rules of `earlier` with the same patterns as one of the rules of `later`, but another
+/// icon, along with that rule. Mapping a pattern twice to the same icon is harmless
+pub(crate) fn duplicate_mappings<'a>(
+    earlier: &'a [Rule],
+    later: &'a [Rule],
+) -> Vec<(&'a Rule, &'a Rule)> {
+    earlier
+        .iter()
+        .filter_map(|earlier| {
+            let later = later
+                .iter()
+                .find(|later| later.patterns == earlier.patterns)?;
+            (later.icon != earlier.icon).then_some((earlier, later))
+        })
+        .collect()
+}
+
+fn read_file(path: &Path) -> Result<String> {
+    let mut buf = String::new();
+    File::open(path)
+        .and_then(|f| BufReader::new(f).read_to_string(&mut buf))
+        .context("Failed to read configuration file")?;
+    Ok(buf)
+}
+
 impl<'de> Deserialize<'de> for Config {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -114,14 +1346,47 @@ impl<'de> Deserialize<'de> for Config {
             {
                 let mut config = Config::default();
                 while let Some((key, value)) = map.next_entry::<String, toml::Value>()? {
+                    // The values lose their position in the file, so at least name their key
+                    let invalid =
+                        |e: toml::de::Error| A::Error::custom(format!("Invalid `{key}`: {e}"));
                     if key == "other" {
-                        config.other = Other::deserialize(value).map_err(A::Error::custom)?;
-                    } else {
+                        config.other = Other::deserialize(value).map_err(invalid)?;
+                    } else if key == "workspace" {
+                        config.workspaces =
+                            BTreeMap::<String, WorkspaceOverrides>::deserialize(value)
+                                .map_err(invalid)?;
+                    } else if key == "extends" {
+                        config.extends = Some(String::deserialize(value).map_err(invalid)?);
+                    } else if key == "include" {
+                        config.include = Vec::<String>::deserialize(value).map_err(invalid)?;
+                    } else if key == "theme" {
+                        config.themes = BTreeMap::<String, Config>::deserialize(value)
+                            .map_err(invalid)?
+                            .into_iter()
+                            .map(|(name, theme)| (name, theme.rules))
+                            .collect();
+                    } else if key == "sway" || key == "i3" {
+                        // Checked right away, rather than once connected to the window manager
+                        Other::deserialize(value.clone()).map_err(invalid)?;
+                        config.backends.insert(
+                            key,
+                            toml::value::Table::deserialize(value).map_err(invalid)?,
+                        );
+                    } else if key == "rule" {
                         config
-                            .mappings
-                            .insert(key, String::deserialize(value).map_err(A::Error::custom)?);
+                            .rules
+                            .extend(Vec::<Rule>::deserialize(value).map_err(invalid)?);
+                    } else {
+                        let icon = String::deserialize(value).map_err(invalid)?;
+                        config.rules.push(Rule {
+                            patterns: vec![key],
+                            icon,
+                            ..Default::default()
+                        });
                     }
                 }
+                // Stable sort: ties are kept in file order
+                config.rules.sort_by_key(|rule| Reverse(rule.priority));
                 Ok(config)
             }
         }
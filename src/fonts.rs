@@ -0,0 +1,62 @@
+//! Check that the icons of the config can be rendered, by asking fontconfig which fonts cover
+//! their characters
+
+use crate::config::{Config, Rule};
+use itertools::Itertools;
+use std::collections::BTreeSet;
+use std::process::Command;
+
+/// Warn about the icons containing characters that no installed font covers, as these show up
+/// as boxes that are easily mistaken for windows that weren't recognised
+pub fn check_fonts(config: &Config) {
+    let icons: BTreeSet<String> = config
+        .rules
+        .iter()
+        .flat_map(Rule::icons)
+        .map(String::as_str)
+        .chain([config.fallback_icon(), config.overflow_icon()])
+        .chain(config.unknown_icons())
+        .chain(config.other.urgent_icon.as_deref())
+        .chain(config.other.fullscreen_icon.as_deref())
+        .chain(config.other.focused_empty_icon.as_deref())
+        .chain(config.other.empty_icon.as_deref())
+        .map(|icon| config.displayed_text(icon).into_owned())
+        .collect();
+    let mut missing = Vec::new();
+    for icon in icons {
+        for c in icon.chars().filter(|&c| needs_font(c)).unique() {
+            match is_covered(c) {
+                Ok(true) => {}
+                Ok(false) => {
+                    missing.push(icon);
+                    break;
+                }
+                Err(e) => {
+                    warn!("Can't check the fonts of the icons with fc-list: {e}");
+                    return;
+                }
+            }
+        }
+    }
+    if !missing.is_empty() {
+        warn!(
+            "No installed font can render icons: {}. Install a font covering them, or pick other icons",
+            missing.iter().map(|icon| format!("\"{icon}\"")).join(", ")
+        );
+    }
+}
+
+/// Whether the character needs a font of its own: ascii is covered by any font, and joiners and
+/// variation selectors aren't rendered by themselves
+fn needs_font(c: char) -> bool {
+    !c.is_ascii() && !matches!(c, '\u{200d}' | '\u{fe00}'..='\u{fe0f}')
+}
+
+/// Whether any installed font covers this character
+fn is_covered(c: char) -> std::io::Result<bool> {
+    let output = Command::new("fc-list")
+        .arg("--format=%{family}\n")
+        .arg(format!(":charset={:x}", c as u32))
+        .output()?;
+    Ok(!output.stdout.is_empty())
+}
@@ -0,0 +1,57 @@
+// Loaded into KWin by workstyle. Reports the virtual desktops and the windows on them back to
+// workstyle over D-Bus whenever they change. `%SERVICE%` is replaced by the D-Bus name of
+// workstyle before loading the script.
+
+function report() {
+    const desktops = workspace.desktops.map((desktop, position) => ({
+        id: desktop.id,
+        name: desktop.name,
+        position: position + 1,
+    }));
+    const windows = workspace
+        .windowList()
+        .filter((window) => window.normalWindow)
+        .map((window) => ({
+            id: window.internalId.toString(),
+            caption: window.caption,
+            desktop_file_name: window.desktopFileName,
+            resource_class: window.resourceClass,
+            resource_name: window.resourceName,
+            desktops: window.desktops.map((desktop) => desktop.id),
+            on_all_desktops: window.onAllDesktops,
+            urgent: window.demandsAttention,
+            focused: window.active,
+            fullscreen: window.fullScreen,
+            x: window.frameGeometry.x,
+            y: window.frameGeometry.y,
+        }));
+    callDBus(
+        "%SERVICE%",
+        "/",
+        "org.workstyle.Kwin",
+        "report",
+        JSON.stringify({
+            desktops: desktops,
+            current: workspace.currentDesktop.id,
+            windows: windows,
+        }),
+    );
+}
+
+function watch(window) {
+    window.captionChanged.connect(report);
+    window.desktopsChanged.connect(report);
+    window.demandsAttentionChanged.connect(report);
+    window.fullScreenChanged.connect(report);
+}
+
+workspace.windowList().forEach(watch);
+workspace.windowAdded.connect((window) => {
+    watch(window);
+    report();
+});
+workspace.windowRemoved.connect(report);
+workspace.windowActivated.connect(report);
+workspace.desktopsChanged.connect(report);
+workspace.currentDesktopChanged.connect(report);
+report();
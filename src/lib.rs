@@ -0,0 +1,1054 @@
+#[macro_use]
+extern crate log;
+
+pub mod config;
+pub mod fonts;
+#[cfg(test)]
+mod tests;
+pub mod window_manager;
+
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::iter::once;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::spawn;
+
+use clap::ValueEnum;
+use config::{Config, DedupBy, DedupCountStyle, IconOrder, Rule, WindowField, DEFAULT_SEPARATOR};
+use indexmap::map::{Entry, IndexMap};
+use itertools::Itertools;
+use notify_rust::Notification;
+use once_cell::sync::Lazy;
+use serde_derive::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+use window_manager::{Change, MatchOptions, Window, WindowId, SCRATCHPAD};
+
+#[derive(ValueEnum, Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnforceWindowManager {
+    #[serde(rename = "sway", alias = "i3")]
+    SwayOrI3,
+    Hyprland,
+    Kwin,
+    Gnome,
+}
+
+/// Whether we've been asked to exit, so the main loop stops and cleans up before exiting
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Whether we've been asked to exit, e.g. by SIGTERM
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::Relaxed)
+}
+
+/// Ask the main loop to stop, e.g. on SIGTERM
+pub fn request_shutdown() {
+    SHUTDOWN.store(true, Ordering::Relaxed);
+}
+
+/// Whether to add the unknown windows to the config file (`--learn`)
+static LEARN: AtomicBool = AtomicBool::new(false);
+
+/// Add the unknown windows to the config file from now on, or stop adding them (`--learn`)
+pub fn set_learn(learn: bool) {
+    LEARN.store(learn, Ordering::Relaxed);
+}
+
+/// How many refreshes a window needs to be shown with the unknown icon for before we notify the
+/// user about it
+const UNKNOWN_REFRESHES_BEFORE_NOTIFYING: usize = 3;
+
+/// A window that was shown with the unknown icon, for `notify_unknown`
+struct UnknownWindow {
+    /// How many refreshes it was shown with the unknown icon for
+    refreshes: usize,
+    /// Its fields, to name it in the notification
+    fields: Vec<String>,
+    /// Whether it still has the unknown icon in the last names we computed
+    unknown: bool,
+}
+
+/// The windows that were shown with the unknown icon, by window id, for `notify_unknown`
+static UNKNOWN_WINDOWS: Lazy<Mutex<HashMap<WindowId, UnknownWindow>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// How many times windows matched a rule, or were unknown by identifier, since the last summary,
+/// for `match_stats_every`
+#[derive(Default)]
+struct MatchStats {
+    passes: usize,
+    matched: usize,
+    unknown: HashMap<String, usize>,
+}
+
+/// How many of the most frequent unknown identifiers the match stats list
+const MATCH_STATS_TOP_UNKNOWN: usize = 5;
+
+static MATCH_STATS: Lazy<Mutex<MatchStats>> = Lazy::new(|| Mutex::new(MatchStats::default()));
+
+/// What naming the workspaces remembers from the names it gave before, besides the names
+/// themselves
+#[derive(Debug, Default)]
+pub struct NamingState {
+    /// The last rule each window was matched to, by window id, for `sticky_icons`
+    sticky_icons: HashMap<WindowId, (usize, String)>,
+    /// The labels of the workspaces, by workspace number, for `keep_label`
+    labels: HashMap<String, String>,
+}
+
+impl NamingState {
+    /// Forget about the windows that were closed, given the windows in each workspace
+    pub fn forget_closed_windows(&mut self, workspaces: &BTreeMap<String, Vec<Window>>) {
+        let ids: HashSet<_> = workspaces
+            .values()
+            .flatten()
+            .filter_map(|window| window.id.as_ref())
+            .collect();
+        self.sticky_icons.retain(|id, _| ids.contains(id));
+        UNKNOWN_WINDOWS
+            .lock()
+            .unwrap()
+            .retain(|id, _| ids.contains(id));
+    }
+}
+
+/// Nothing remembered, as before naming the workspaces for the first time
+static NOTHING_REMEMBERED: Lazy<NamingState> = Lazy::new(NamingState::default);
+
+/// How a window got its icon, for `--explain`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    /// It matched this pattern
+    Pattern(String),
+    /// It doesn't match any rule anymore, but keeps its icon (`sticky_icons`)
+    Sticky,
+    /// It is similar enough to a pattern of the rule, with this score (`fuzzy`)
+    Fuzzy(f64),
+    /// It matches no rule, so it gets the unknown icon
+    Unknown,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Pattern(pattern) => write!(f, "matched pattern: \"{pattern}\""),
+            Reason::Sticky => write!(f, "kept its icon as it matches no rule anymore"),
+            Reason::Fuzzy(score) => write!(f, "fuzzy matched (score: {score:.2})"),
+            Reason::Unknown => write!(f, "matched no rule, fell back to the unknown icon"),
+        }
+    }
+}
+
+/// Find the icon for this window, along with the position of the matching rule in the config
+/// (None if the unknown icon was used) and the reason it got this icon, given what we remember
+/// from the names we gave before. Remembering the icon, or reporting the window if it is
+/// unknown, is left to `Effects::apply`
+pub fn match_window(
+    config: &Config,
+    state: &NamingState,
+    window: &Window,
+) -> (Option<usize>, String, Reason) {
+    let sticky_id = window.id.as_ref().filter(|_| config.other.sticky_icons);
+    // The rules matching the window, in order of precedence, only against this field if any
+    let matching_rules = |field: Option<WindowField>| {
+        let options = MatchOptions {
+            field,
+            ..config.match_options()
+        };
+        config
+            .rules
+            .iter()
+            .enumerate()
+            .filter_map(move |(position, rule)| {
+                let pattern = rule.matching_pattern(window, options)?;
+                Some((position, pattern, &rule.icon))
+            })
+    };
+    // With `match_priority`, the fields are consulted one at a time in this order and the first
+    // one matching a rule wins. Then, all the fields are consulted at once
+    let (field, matches): (_, Vec<_>) = config
+        .other
+        .match_priority
+        .iter()
+        .map(|&field| Some(field))
+        .chain(once(None))
+        .map(|field| (field, matching_rules(field).peekable()))
+        .find_map(|(field, mut matches)| {
+            matches.peek().is_some().then(|| (field, matches.collect()))
+        })
+        .unwrap_or_default();
+    if let Some(&(mut position, mut pattern, _)) = matches.first() {
+        let icon = if config.other.compose_matches {
+            // The icons of all the matching rules, each only once
+            let icon = matches.iter().map(|(_, _, icon)| icon).unique().join("");
+            debug!(
+                "Window: {window:?} matched {} rules, composing their icons into: \"{icon}\"",
+                matches.len()
+            );
+            icon
+        } else {
+            // A rule naming the app id exactly wins over the rules of the same priority that come
+            // before it, which may only match part of the title. Unless `match_priority` had
+            // another field decide
+            let by_app_id = matches!(field, None | Some(WindowField::AppId));
+            let priority = config.rules[position].priority;
+            if let Some(&(exact, exact_pattern, _)) = matches
+                .iter()
+                .take_while(|(other, _, _)| config.rules[*other].priority == priority)
+                .find(|(other, _, _)| {
+                    by_app_id && config.rules[*other].matches_app_id(window, config.match_options())
+                })
+            {
+                position = exact;
+                pattern = exact_pattern;
+            }
+            let icon = config.rules[position].icon.clone();
+            if log_enabled!(log::Level::Debug) {
+                // The other rules matching the window, which the winning one shadows
+                let shadowed = matches
+                    .iter()
+                    .filter(|(other, _, _)| *other != position)
+                    .map(|(_, pattern, icon)| format!("\"{pattern}\" (icon: \"{icon}\")"))
+                    .join(", ");
+                debug!(
+                    "Window: {window:?} matched pattern: \"{pattern}\" with icon: \"{icon}\", shadowing: {}",
+                    if shadowed.is_empty() { "no other pattern" } else { &shadowed }
+                );
+            }
+            icon
+        };
+        return (Some(position), icon, Reason::Pattern(pattern.to_string()));
+    }
+    if let Some((position, icon)) = sticky_id.and_then(|id| state.sticky_icons.get(id).cloned()) {
+        debug!("Keeping icon: {icon} for window: {window:?} as it doesn't match any rule anymore");
+        return (Some(position), icon, Reason::Sticky);
+    }
+    if config.other.fuzzy {
+        if let Some((position, score)) = fuzzy_match_window(config, window) {
+            let rule = &config.rules[position];
+            info!(
+                "Fuzzy matched window: {window:?} to patterns: {:?} (score: {score:.2})",
+                rule.patterns
+            );
+            info!("Consider adding an exact pattern for this window in your config file!");
+            return (Some(position), rule.icon.clone(), Reason::Fuzzy(score));
+        }
+    }
+    (
+        None,
+        config.unknown_icon_for(window).into(),
+        Reason::Unknown,
+    )
+}
+
+/// What naming the workspaces leaves to be remembered or reported, so `name_workspaces` has no
+/// side effects. Its caller applies them once the names are computed
+#[derive(Default)]
+pub struct Effects<'a> {
+    /// Each window, along with the position of the rule it matched, its icon and why it got it,
+    /// as given by `match_window`
+    matches: Vec<(&'a Window, Option<usize>, String, Reason)>,
+    /// The label found in the name of each workspace, by workspace number, for `keep_label`
+    labels: Vec<(String, Option<String>)>,
+}
+
+impl Effects<'_> {
+    /// Remember the icons and labels for the next names, count the matches in the match stats and
+    /// report the unknown windows
+    pub fn apply(self, config: &Config, state: &mut NamingState) {
+        if config.other.notify_unknown {
+            // Only the windows reported below still have the unknown icon
+            for window in UNKNOWN_WINDOWS.lock().unwrap().values_mut() {
+                window.unknown = false;
+            }
+        }
+        for (window, position, icon, reason) in self.matches {
+            if config.other.match_stats_every.is_some() {
+                record_match(window, position.is_some());
+            }
+            match (position, reason) {
+                (Some(position), Reason::Pattern(_)) => {
+                    if let Some(id) = window.id.as_ref().filter(|_| config.other.sticky_icons) {
+                        state.sticky_icons.insert(id.clone(), (position, icon));
+                    }
+                }
+                (_, Reason::Unknown) => report_unknown(config, window),
+                _ => {}
+            }
+        }
+        for (num, label) in self.labels {
+            match label {
+                Some(label) => state.labels.insert(num, label),
+                None => state.labels.remove(&num),
+            };
+        }
+    }
+}
+
+/// A pass naming the workspaces: what it remembers from the names given before, and what it
+/// leaves to be applied once it's done
+pub(crate) struct Naming<'s, 'a> {
+    state: &'s NamingState,
+    effects: Effects<'a>,
+}
+
+impl<'s> Naming<'s, '_> {
+    fn new(state: &'s NamingState) -> Self {
+        Self {
+            state,
+            effects: Effects::default(),
+        }
+    }
+}
+
+impl Default for Naming<'_, '_> {
+    fn default() -> Self {
+        Self::new(&NOTHING_REMEMBERED)
+    }
+}
+
+/// Log about, notify about and learn a window that matches no rule, as configured
+fn report_unknown(config: &Config, window: &Window) {
+    if config.log_unknown() {
+        log_unknown(window);
+    }
+    if config.other.notify_unknown {
+        mark_unknown(window);
+    }
+    if LEARN.load(Ordering::Relaxed) {
+        if let Err(e) = Config::learn(window) {
+            error!("Failed to add window: {window:?} to the config file: {e:#}");
+        }
+    }
+}
+
+/// The windows that matched no rule we've logged about, by their fields
+static LOGGED_UNKNOWN: Lazy<Mutex<HashSet<Vec<String>>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Log about a window that matches no rule, unless we already did: a workspace full of them would
+/// flood the log otherwise, as it is named again on every event
+fn log_unknown(window: &Window) {
+    let fields = window.fields().map(str::to_string).collect();
+    if LOGGED_UNKNOWN.lock().unwrap().insert(fields) {
+        error!("Couldn't identify window: {window:?}");
+        info!("Make sure to add an icon for this file in your config file!");
+    }
+}
+
+/// Note that a window has the unknown icon in the names we just computed, for `notify_unknown`
+fn mark_unknown(window: &Window) {
+    if let Some(id) = &window.id {
+        UNKNOWN_WINDOWS
+            .lock()
+            .unwrap()
+            .entry(id.clone())
+            .or_insert_with(|| UnknownWindow {
+                refreshes: 0,
+                fields: window.fields().map(str::to_string).collect(),
+                unknown: true,
+            })
+            .unknown = true;
+    }
+}
+
+/// Count a refresh for each window that still has the unknown icon, and send a desktop
+/// notification about the ones that have had it for a while. This happens only once per window
+fn notify_unknown() {
+    let mut unknown_windows = UNKNOWN_WINDOWS.lock().unwrap();
+    for window in unknown_windows.values_mut().filter(|window| window.unknown) {
+        window.refreshes += 1;
+        if window.refreshes == UNKNOWN_REFRESHES_BEFORE_NOTIFYING {
+            let body = format!(
+                "Add an icon for one of {} to your config file",
+                window
+                    .fields
+                    .iter()
+                    .map(|field| format!("\"{field}\""))
+                    .join(", ")
+            );
+            // Don't block the main loop on the notification daemon
+            spawn(move || {
+                if let Err(e) = Notification::new()
+                    .summary("workstyle: unknown window")
+                    .body(&body)
+                    .show()
+                {
+                    warn!("Failed to send notification: {e}");
+                }
+            });
+        }
+    }
+}
+
+/// Find the rule with the pattern most similar to one of the window's fields (or to one of their
+/// words), provided the similarity is above the configured threshold
+fn fuzzy_match_window(config: &Config, window: &Window) -> Option<(usize, f64)> {
+    let threshold = config.fuzzy_threshold();
+    let fields: Vec<String> = window.fields().map(str::to_lowercase).collect();
+    config
+        .rules
+        .iter()
+        .enumerate()
+        .filter(|(_, rule)| !rule.excludes(window, config.match_options()))
+        .flat_map(|(position, rule)| {
+            rule.patterns
+                .iter()
+                .map(move |pattern| (position, pattern.to_lowercase()))
+        })
+        .filter_map(|(position, pattern)| {
+            fields
+                .iter()
+                .flat_map(|field| once(field.as_str()).chain(field.split_whitespace()))
+                .map(|candidate| strsim::jaro_winkler(candidate, &pattern))
+                .max_by(f64::total_cmp)
+                .map(|score| (position, score))
+        })
+        .filter(|(_, score)| *score >= threshold)
+        // On ties, keep the first rule in order of precedence
+        .min_by(|(_, l), (_, r)| r.total_cmp(l))
+}
+
+#[cfg(test)]
+fn pretty_window(config: &Config, window: &Window) -> String {
+    match_window(config, &NOTHING_REMEMBERED, window).1
+}
+
+/// An icon to display, along with what we know of the window(s) it stands for
+struct Icon {
+    /// Position of the matching rule in the config (None for the unknown icon)
+    position: Option<usize>,
+    icon: String,
+    focused: bool,
+    /// Number of windows this icon stands for, once deduplicated
+    count: usize,
+}
+
+fn pretty_windows<'a>(
+    config: &Config,
+    windows: &'a [Window],
+    naming: &mut Naming<'_, 'a>,
+) -> String {
+    let mut icons: Vec<_> = windows
+        .iter()
+        .map(|window| {
+            let (position, icon, reason) = match_window(config, naming.state, window);
+            naming
+                .effects
+                .matches
+                .push((window, position, icon.clone(), reason));
+            Icon {
+                position,
+                icon,
+                focused: window.focused,
+                count: 1,
+            }
+        })
+        .collect();
+    let windows_per_rule = icons.iter().filter_map(|icon| icon.position).counts();
+    for icon in &mut icons {
+        if let Some(position) = icon.position {
+            if let Some(count_icon) = config
+                .rules
+                .get(position)
+                .and_then(|rule| rule.icon_for_count(windows_per_rule[&position]))
+            {
+                icon.icon = count_icon.to_string();
+            }
+        }
+    }
+    // Windows mapped to an empty icon are hidden on purpose
+    icons.retain(|icon| !icon.icon.is_empty());
+    match config.other.icon_order {
+        IconOrder::Window => {}
+        // Windows that match no rule go last
+        IconOrder::Config => icons.sort_by_key(|icon| icon.position.unwrap_or(usize::MAX)),
+        IconOrder::Alpha => icons.sort_by(|l, r| l.icon.cmp(&r.icon)),
+    }
+    if config.other.deduplicate_icons {
+        icons = deduplicate(icons, config.other.dedup_by);
+    }
+    // After deduplicating, so the focused and unfocused windows of an app still share an icon,
+    // which is the focused icon if any of them has the focus
+    for icon in icons.iter_mut().filter(|icon| icon.focused) {
+        if let Some(rule) = icon
+            .position
+            .and_then(|position| config.rules.get(position))
+        {
+            // Icons for counts, or composed of the icons of several rules, take precedence
+            if let Some(focused_icon) = rule
+                .focused_icon
+                .as_ref()
+                .filter(|_| icon.icon == rule.icon)
+            {
+                icon.icon = focused_icon.clone();
+            }
+        }
+    }
+    if config.other.deduplicate_icons {
+        for icon in &mut icons {
+            icon.icon = with_count(&icon.icon, icon.count, config.other.dedup_count_style);
+        }
+    }
+    let mut counts = HashMap::new();
+    icons.retain(|icon| {
+        let max = icon
+            .position
+            .and_then(|position| config.rules.get(position))
+            .and_then(|rule| rule.max);
+        match (icon.position, max) {
+            (Some(position), Some(max)) => {
+                let count = counts.entry(position).or_insert(0);
+                *count += 1;
+                *count <= max
+            }
+            _ => true,
+        }
+    });
+    if config.other.compact_runs {
+        icons = compact_runs(icons);
+    }
+    let mut s = String::new();
+    if let Some(urgent_icon) = &config.other.urgent_icon {
+        if windows.iter().any(|window| window.urgent) {
+            s.push_str(urgent_icon);
+            s.push(' ');
+        }
+    }
+    let mut width = 0;
+    for Icon { icon, focused, .. } in icons {
+        let icon = match config.other.icon_padding.get(&icon) {
+            Some(padding) => format!(
+                "{}{icon}{}",
+                " ".repeat(padding.left),
+                " ".repeat(padding.right)
+            ),
+            None => icon,
+        };
+        if let Some(max_width) = config.other.max_width {
+            width += icon_width(&config.displayed_text(&icon));
+            if width > max_width {
+                s.push_str(config.overflow_icon());
+                s.push(' ');
+                break;
+            }
+            // The space after the icon
+            width += 1;
+        }
+        match &config.other.focused_wrap {
+            Some(wrap) if focused => s.push_str(&wrap.replace("{icon}", &icon)),
+            _ => s.push_str(&icon),
+        }
+        s.push(' ');
+    }
+    if let Some(fullscreen_icon) = &config.other.fullscreen_icon {
+        if windows.iter().any(|window| window.fullscreen) {
+            s.push_str(fullscreen_icon);
+            s.push(' ');
+        }
+    }
+    s
+}
+
+/// Count a window in the match stats
+fn record_match(window: &Window, matched: bool) {
+    let mut stats = MATCH_STATS.lock().unwrap();
+    if matched {
+        stats.matched += 1;
+    } else {
+        // The identifiers are what a mapping would use, the title only comes last
+        let id = [
+            &window.app_id,
+            &window.window_properties_class,
+            &window.window_properties_instance,
+            &window.name,
+        ]
+        .into_iter()
+        .find_map(|field| field.clone())
+        .unwrap_or_default();
+        *stats.unknown.entry(id).or_default() += 1;
+    }
+}
+
+/// Log how many windows matched a rule since the last summary, then start over
+pub fn log_match_stats() {
+    let mut stats = MATCH_STATS.lock().unwrap();
+    let unknown: usize = stats.unknown.values().sum();
+    let top = stats
+        .unknown
+        .iter()
+        .sorted_by_key(|(id, count)| (Reverse(**count), id.as_str()))
+        .take(MATCH_STATS_TOP_UNKNOWN)
+        .map(|(id, count)| format!("\"{id}\" ({count})"))
+        .join(", ");
+    info!(
+        "{} out of {} windows matched a rule over the last {} passes. Most frequent unknown windows: {}",
+        stats.matched,
+        stats.matched + unknown,
+        stats.passes,
+        if top.is_empty() { "none" } else { &top }
+    );
+    *stats = MatchStats::default();
+}
+
+/// Count a pass, including the ones skipped as they would compute the same names as the last one.
+/// This logs the match stats every `match_stats_every` passes, if it is set (0 means only on
+/// SIGUSR2), and counts a refresh for the windows with the unknown icon for `notify_unknown`
+pub fn count_pass(config: &Config) {
+    if let Some(every) = config.other.match_stats_every {
+        let passes = {
+            let mut stats = MATCH_STATS.lock().unwrap();
+            stats.passes += 1;
+            stats.passes
+        };
+        if every != 0 && passes >= every {
+            log_match_stats();
+        }
+    }
+    if config.other.notify_unknown {
+        notify_unknown();
+    }
+}
+
+/// Keep only the first occurrence of each icon, in order.
+/// As this happens after sorting, the icons come in the order given by `icon_order` either way
+fn deduplicate(icons: Vec<Icon>, dedup_by: DedupBy) -> Vec<Icon> {
+    let mut deduplicated: IndexMap<(Option<usize>, String), Icon> = IndexMap::new();
+    for icon in icons {
+        let key = match dedup_by {
+            DedupBy::Icon => (None, icon.icon.clone()),
+            // Windows that match no rule are still deduplicated by icon
+            DedupBy::App => (icon.position, icon.icon.clone()),
+        };
+        match deduplicated.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let deduplicated = entry.get_mut();
+                // A deduplicated icon is focused if any of the windows it stands for is
+                deduplicated.focused |= icon.focused;
+                deduplicated.count += 1;
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(icon);
+            }
+        }
+    }
+    deduplicated.into_values().collect()
+}
+
+/// Collapse the runs of identical adjacent icons into the icon followed by the length of the run,
+/// like `🦊×2`. Unlike deduplicating, this keeps the icons in the same place
+fn compact_runs(icons: Vec<Icon>) -> Vec<Icon> {
+    let mut runs: Vec<(Icon, usize)> = Vec::new();
+    for icon in icons {
+        match runs.last_mut() {
+            Some((last, length)) if last.icon == icon.icon => {
+                // A run is focused if any of the windows it stands for is
+                last.focused |= icon.focused;
+                last.count += icon.count;
+                *length += 1;
+            }
+            _ => runs.push((icon, 1)),
+        }
+    }
+    runs.into_iter()
+        .map(|(mut icon, length)| {
+            if length > 1 {
+                icon.icon = format!("{}×{length}", icon.icon);
+            }
+            icon
+        })
+        .collect()
+}
+
+/// The icon, showing how many windows it stands for in the given style
+fn with_count(icon: &str, count: usize, style: DedupCountStyle) -> String {
+    const SUPERSCRIPT_DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+    match style {
+        _ if count == 1 => icon.to_string(),
+        DedupCountStyle::None => icon.to_string(),
+        DedupCountStyle::Superscript => {
+            let superscript: String = count
+                .to_string()
+                .chars()
+                .filter_map(|digit| digit.to_digit(10))
+                .map(|digit| SUPERSCRIPT_DIGITS[digit as usize])
+                .collect();
+            format!("{icon}{superscript}")
+        }
+        DedupCountStyle::Prefix => format!("{count}×{icon}"),
+    }
+}
+
+/// The icons of the workspace with this number, starting with its fixed label if it has one
+fn workspace_icons<'a>(
+    config: &Config,
+    num: &str,
+    windows: &'a [Window],
+    naming: &mut Naming<'_, 'a>,
+) -> String {
+    let mut icons = pretty_windows(config, windows, naming);
+    if let Some(prefix) = config
+        .workspace_overrides(num)
+        .and_then(|overrides| overrides.prefix.as_deref())
+    {
+        icons.insert_str(0, &format!("{prefix} "));
+    }
+    icons
+}
+
+/// Number of columns an icon takes on screen.
+/// Characters following a zero width joiner are rendered as part of the same glyph as the
+/// preceding ones and the emoji variation selector makes the preceding character double width
+fn icon_width(icon: &str) -> usize {
+    const ZERO_WIDTH_JOINER: char = '\u{200d}';
+    const EMOJI_VARIATION_SELECTOR: char = '\u{fe0f}';
+    let mut width = 0;
+    let mut joined = false;
+    let mut chars = icon.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !joined {
+            width += match chars.peek() {
+                Some(&EMOJI_VARIATION_SELECTOR) => 2,
+                _ => c.width().unwrap_or(0),
+            };
+        }
+        joined = c == ZERO_WIDTH_JOINER;
+    }
+    width
+}
+
+/// Path of a file private to this user's session, e.g. `workstyle.lock`. Outside of
+/// `$XDG_RUNTIME_DIR`, the file name gets the uid, so users sharing `/tmp` don't collide
+pub fn runtime_file(stem: &str, extension: &str) -> PathBuf {
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(dirs::runtime_dir);
+    match runtime_dir {
+        Some(dir) => dir.join(format!("{stem}.{extension}")),
+        None => {
+            let uid = fs::metadata("/proc/self").map_or(0, |metadata| metadata.uid());
+            PathBuf::from("/tmp").join(format!("{stem}-{uid}.{extension}"))
+        }
+    }
+}
+
+/// The number of a workspace, which we keep at the start of its name.
+/// The leading digits are the number whether the workspace was never renamed (`3`, or `3:` with
+/// a stray colon), renamed by us (`3: 🦊 `, whatever the separator was then) or named by the user
+/// (`3: code`), so a separator made of digits can't cut the number short.
+/// Workspaces whose name doesn't start with a number, or that are named the i3 way
+/// (`<number>:<name>`), keep what comes before the separator.
+fn workspace_number<'a>(name: &'a str, sep: &str) -> &'a str {
+    let digits = name
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(name.len());
+    if digits > 0 && !has_i3_label(name) {
+        &name[..digits]
+    } else {
+        name.split(name_separator(sep, name)).next().unwrap_or(name)
+    }
+}
+
+/// The number of the workspace with this name. Workspaces with a `[workspace.<number>]`
+/// separator are named with it, so it is the one to read their number back with
+pub fn workspace_number_in<'a>(config: &Config, name: &'a str) -> &'a str {
+    let num = workspace_number(name, config.separator());
+    if config.workspace_overrides(num).is_some() {
+        return num;
+    }
+    config
+        .workspaces
+        .iter()
+        .filter(|(_, overrides)| overrides.separator.is_some())
+        .map(|(key, overrides)| {
+            let num = workspace_number(name, config.for_workspace(key).separator());
+            (num, overrides)
+        })
+        .find(|(num, overrides)| {
+            config
+                .workspace_overrides(num)
+                .map_or(false, |found| std::ptr::eq(found, *overrides))
+        })
+        .map_or(num, |(num, _)| num)
+}
+
+/// Whether the name of this workspace follows the i3 convention for named workspaces:
+/// `<number>:<name>`, as in `1:web`
+fn has_i3_label(name: &str) -> bool {
+    name.trim_start_matches(|c: char| c.is_ascii_digit())
+        .strip_prefix(':')
+        .map_or(false, |label| label.starts_with(char::is_alphanumeric))
+        && name.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// The separator between the number of the workspace with this name and its icons.
+/// Without a separator, we couldn't tell where the name of a workspace without a number ends, so
+/// these use the default separator instead
+fn name_separator<'a>(sep: &'a str, name: &str) -> &'a str {
+    if sep.is_empty() && (!name.starts_with(|c: char| c.is_ascii_digit()) || has_i3_label(name)) {
+        DEFAULT_SEPARATOR
+    } else {
+        sep
+    }
+}
+
+/// Left pad a workspace number with zeros to the given width.
+/// Non numeric prefixes are left untouched.
+pub fn pad_number(num: &str, width: Option<usize>) -> String {
+    match (num.parse::<u64>(), width) {
+        (Ok(n), Some(width)) => format!("{n:0width$}"),
+        _ => num.to_string(),
+    }
+}
+
+/// A hash of everything the names of the workspaces depend on: the config files, the windows
+/// in each workspace and what identifies them. The state of the windows, e.g. which one has the
+/// focus, only counts when the config shows it
+pub fn fingerprint(
+    config: &Config,
+    workspaces: &BTreeMap<String, Vec<Window>>,
+    focused: Option<&str>,
+) -> u64 {
+    let other = &config.other;
+    let shows_focus =
+        other.focused_wrap.is_some() || config.rules.iter().any(|rule| rule.focused_icon.is_some());
+    let shows_fullscreen = other.fullscreen_icon.is_some()
+        || config.rules.iter().any(|rule| rule.fullscreen.is_some());
+    let shows_floating = config.rules.iter().any(|rule| rule.floating.is_some());
+    let mut hasher = DefaultHasher::new();
+    config.source_hash().hash(&mut hasher);
+    for (name, windows) in workspaces {
+        name.hash(&mut hasher);
+        windows.len().hash(&mut hasher);
+        for window in windows {
+            window.name.hash(&mut hasher);
+            window.app_id.hash(&mut hasher);
+            window.window_properties_class.hash(&mut hasher);
+            window.window_properties_instance.hash(&mut hasher);
+            window.marks.hash(&mut hasher);
+            // Sticky icons are kept by window
+            other.sticky_icons.then_some(&window.id).hash(&mut hasher);
+            (other.urgent_icon.is_some() && window.urgent).hash(&mut hasher);
+            (shows_focus && window.focused).hash(&mut hasher);
+            (shows_fullscreen && window.fullscreen).hash(&mut hasher);
+            (shows_floating && window.floating).hash(&mut hasher);
+        }
+    }
+    focused.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// With partial updates, the only workspace to rename: the one of the window the last event was
+/// about, when we know which window that is. They're all renamed when the config files changed
+/// since the last renames, given the hash of these files, as all the names may depend on them
+pub fn touched_workspace(
+    config: &Config,
+    workspaces: &BTreeMap<String, Vec<Window>>,
+    change: &Change,
+    last_source_hash: Option<u64>,
+) -> Option<String> {
+    if last_source_hash != Some(config.source_hash()) {
+        return None;
+    }
+    match change {
+        Change::Window(id) if config.other.partial_updates => workspaces
+            .iter()
+            .find(|(_, windows)| windows.iter().any(|window| window.id.as_ref() == Some(id)))
+            .map(|(name, _)| name.clone()),
+        _ => None,
+    }
+}
+
+/// What we name the workspaces with, as computed by `name_workspaces`
+#[derive(Default)]
+pub struct Names<'a> {
+    /// The new name of each workspace, by current name. The scratchpad is left out, as it can't
+    /// be renamed
+    pub names: BTreeMap<String, String>,
+    /// The same names, by workspace number padded to `number_width`, for the status socket
+    pub by_number: BTreeMap<String, String>,
+    /// The icons following the number in each of these names, by workspace number, for the JSON
+    /// output. The scratchpad only has the icons of its windows, as it has no name
+    pub icons: BTreeMap<String, String>,
+    /// What's left for the caller to apply
+    pub effects: Effects<'a>,
+}
+
+/// The new name of each workspace, by current name, given the windows in each workspace. This
+/// does all the naming, without talking to the window manager and without side effects, as for
+/// the first names we give: nothing is remembered from before and no workspace has the focus
+pub fn compute_workspace_names(
+    config: &Config,
+    workspaces: &BTreeMap<String, Vec<Window>>,
+) -> BTreeMap<String, String> {
+    name_workspaces(config, &NamingState::default(), workspaces, None).names
+}
+
+/// The names of the workspaces, given what we remember from the names we gave before, the
+/// windows in each workspace and the focused workspace, for all of the outputs. Like
+/// `compute_workspace_names`, this has no side effects
+pub fn name_workspaces<'a>(
+    config: &Config,
+    state: &NamingState,
+    workspaces: &'a BTreeMap<String, Vec<Window>>,
+    focused: Option<&str>,
+) -> Names<'a> {
+    let mut naming = Naming::new(state);
+    let mut names = Names::default();
+    for (name, windows) in workspaces {
+        let num = workspace_number_in(config, name);
+        let workspace_config = config.for_workspace(num);
+        if name == SCRATCHPAD {
+            let icons = workspace_icons(&workspace_config, num, windows, &mut naming);
+            names
+                .icons
+                .insert(num.to_string(), icons.trim_end().to_string());
+            continue;
+        }
+        let focused = focused == Some(name.as_str());
+        let icons =
+            new_workspace_icons(&workspace_config, name, num, windows, focused, &mut naming);
+        let new_name = workspace_name(&workspace_config, num, &icons);
+        names.by_number.insert(
+            pad_number(num, workspace_config.other.number_width),
+            new_name.clone(),
+        );
+        names.names.insert(name.clone(), new_name);
+        names
+            .icons
+            .insert(num.to_string(), icons.trim_end().to_string());
+    }
+    names.effects = naming.effects;
+    names
+}
+
+/// The name the workspace with this name had before we renamed it: its plain number, along with
+/// its label with `keep_label`
+pub fn original_name(config: &Config, state: &NamingState, name: &str) -> String {
+    let num = workspace_number_in(config, name);
+    let workspace_config = config.for_workspace(num);
+    // Without the padding we added
+    let plain = num
+        .parse::<u64>()
+        .map_or_else(|_| num.to_string(), |num| num.to_string());
+    let label = workspace_config
+        .other
+        .keep_label
+        .then(|| workspace_label(&workspace_config, state, name, num))
+        .flatten();
+    match label {
+        Some(label) => {
+            let sep = name_separator(workspace_config.separator(), &plain);
+            format!("{plain}{sep}{label}")
+        }
+        None => plain,
+    }
+}
+
+/// The label the user gave to the workspace with this name, between its number and the icons we
+/// added after it, e.g. `code` for `3: code 🦊`, given the labels we remembered. Remembering
+/// this one is left to `Effects::apply`
+fn workspace_label(config: &Config, state: &NamingState, name: &str, num: &str) -> Option<String> {
+    let rest = name.strip_prefix(num)?;
+    let rest = rest.strip_prefix(config.separator()).unwrap_or(rest).trim();
+    if let Some(label) = state.labels.get(num) {
+        // A name we gave, which starts with the label we found before
+        if rest == label || rest.starts_with(&format!("{label} ")) {
+            return Some(label.clone());
+        }
+    }
+    // The user named the workspace, or we named it before restarting: the trailing words that
+    // are icons of ours aren't part of the label
+    let icons: HashSet<&str> = config
+        .rules
+        .iter()
+        .flat_map(Rule::icons)
+        .map(String::as_str)
+        .chain(once(config.fallback_icon()))
+        .chain(config.unknown_icons())
+        .collect();
+    let mut words: Vec<_> = rest.split(' ').collect();
+    while words
+        .last()
+        .map_or(false, |word| word.is_empty() || icons.contains(word))
+    {
+        words.pop();
+    }
+    let label = words.join(" ");
+    (!label.is_empty()).then_some(label)
+}
+
+/// The icons following the number in the name we give to the workspace with this name and
+/// number, given the config for this workspace. This is what the JSON output shows
+fn new_workspace_icons<'a>(
+    config: &Config,
+    name: &str,
+    num: &str,
+    windows: &'a [Window],
+    focused: bool,
+    naming: &mut Naming<'_, 'a>,
+) -> String {
+    let mut icons = workspace_icons(config, num, windows, naming);
+    if let Some(template) = &config.other.empty_icon {
+        if windows.is_empty() {
+            icons = format!("{} ", template.replace("{num}", num));
+        }
+    }
+    if let Some(icon) = &config.other.focused_empty_icon {
+        if focused && windows.is_empty() {
+            icons = format!("{icon} ");
+        }
+    }
+    if config.other.keep_label {
+        let label = workspace_label(config, naming.state, name, num);
+        if let Some(label) = &label {
+            icons = format!("{label} {icons}");
+        }
+        naming.effects.labels.push((num.to_string(), label));
+    }
+    if let Some(max_len) = config.other.max_name_len {
+        if !icons.is_empty() {
+            let prefix = name_prefix(config, num);
+            icons = truncate_icons(&prefix, &icons, max_len, config.overflow_icon());
+        }
+    }
+    icons
+}
+
+/// The name of the workspace with this number, given the config for this workspace and the icons
+/// following its number: the number alone if there are none
+fn workspace_name(config: &Config, num: &str, icons: &str) -> String {
+    if icons.is_empty() {
+        pad_number(num, config.other.number_width)
+    } else {
+        format!("{}{icons}", name_prefix(config, num))
+    }
+}
+
+/// What comes before the icons in the name of the workspace with this number: the number, padded
+/// to `number_width`, and the separator
+fn name_prefix(config: &Config, num: &str) -> String {
+    let num = pad_number(num, config.other.number_width);
+    format!("{num}{}", name_separator(config.separator(), &num))
+}
+
+/// Cut the icons so the name made of the prefix and the icons is at most `max_len` grapheme
+/// clusters long, ending it with the overflow icon. The prefix, with the number, is never cut
+fn truncate_icons(prefix: &str, icons: &str, max_len: usize, overflow_icon: &str) -> String {
+    let len = |s: &str| s.graphemes(true).count();
+    if len(prefix) + len(icons.trim_end()) <= max_len {
+        return icons.to_string();
+    }
+    let room = max_len.saturating_sub(len(prefix) + len(overflow_icon));
+    let kept: String = icons.graphemes(true).take(room).collect();
+    format!("{}{overflow_icon}", kept.trim_end())
+}
@@ -1,26 +1,38 @@
 #[macro_use]
 extern crate log;
 
-mod config;
-#[cfg(test)]
-mod tests;
-mod window_manager;
-
-use std::collections::HashSet;
-use std::path::PathBuf;
-use std::process::exit;
-use std::sync::Mutex;
+mod status;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{stdout, ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, exit};
+use std::sync::{Mutex, Once};
 use std::thread::{sleep, spawn};
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
-use config::Config;
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use env_logger::Target;
+use itertools::Itertools;
 use lockfile::Lockfile;
-use once_cell::sync::Lazy;
-use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+use log::LevelFilter;
+use serde_derive::{Deserialize, Serialize};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR2};
 use signal_hook::iterator::Signals;
-use window_manager::{Window, WindowManager, WM};
+use workstyle::config::{self, Config, Other};
+use workstyle::fonts;
+use workstyle::window_manager::{Change, Window, WindowManager, SCRATCHPAD, WM};
+use workstyle::{
+    count_pass, fingerprint, log_match_stats, match_window, name_workspaces, original_name,
+    pad_number, request_shutdown, runtime_file, set_learn, shutdown_requested, touched_workspace,
+    workspace_number_in, EnforceWindowManager, Names, NamingState,
+};
 
 /// Workspaces with style!
 ///
@@ -32,7 +44,8 @@ use window_manager::{Window, WindowManager, WM};
 ///
 /// The short description of each program is configurable. In the absence of a
 /// config file, one will be generated automatically.
-/// See ${XDG_CONFIG_HOME}/workstyle/config.yml for  details.
+/// See ${XDG_CONFIG_HOME}/workstyle/config.toml for details. A legacy config.yml
+/// is still read when there is no config.toml.
 ///
 /// If you prefer not to have multiple copies of the same icon when there are
 /// multiple matching windows, set this config option:
@@ -44,118 +57,715 @@ use window_manager::{Window, WindowManager, WM};
 struct Args {
     #[arg(short, long)]
     enforce_window_manager: Option<EnforceWindowManager>,
+    /// Path of the sway/i3 ipc socket to connect to, instead of the one from $SWAYSOCK or $I3SOCK
+    #[arg(long)]
+    socket: Option<PathBuf>,
+    /// What to do with the computed workspace names
+    #[arg(short, long, value_enum, default_value_t = Output::Rename)]
+    output: Output,
+    /// Give up after failing to connect to the window manager this many times in a row (0 means
+    /// never give up)
+    #[arg(long, default_value_t = 0)]
+    max_connect_attempts: usize,
+    /// Add a commented out mapping to the config file for each window that matches no rule
+    #[arg(long)]
+    learn: bool,
+    /// Print the windows as seen by the window manager, to debug how they are identified, then
+    /// exit
+    #[arg(long)]
+    dump_tree: bool,
+    /// Don't take the lock that prevents running several instances at once. Instances renaming
+    /// the workspaces of the same window manager will fight each other
+    #[arg(long)]
+    no_lock: bool,
+    /// Read the config from this file instead of the one from the config directory, or from stdin
+    /// for `-`
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Print what each workspace would be renamed to, then exit without renaming anything
+    #[arg(long)]
+    status: bool,
+    /// Like --status, also printing for each window which rule it matched, or why it got the
+    /// unknown icon
+    #[arg(long)]
+    explain: bool,
+    /// Check the config file, then exit with 0 if it is fine, 2 if it can't be parsed, 3 if the
+    /// separator is contained in an icon or 5 if there is none. The first line printed is `ok`,
+    /// `parse-error`, `separator-conflict` or `missing`, followed by the path of the config file.
+    /// The details follow
+    #[arg(long)]
+    config_check: bool,
+    /// Print which window manager we connect to, to check it is detected correctly, then exit
+    #[arg(long)]
+    which_wm: bool,
+    /// Use the rules of this `[theme.<name>]` from the config, instead of `active_theme`
+    #[arg(long)]
+    theme: Option<String>,
+    /// Log more: -v for info, -vv for debug and -vvv for trace. RUST_LOG takes precedence
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(ValueEnum, Debug, Clone, Copy)]
-pub enum EnforceWindowManager {
-    SwayOrI3,
-    Hyprland,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert a legacy config.yml to a config.toml next to it
+    Migrate,
 }
 
-static LOCK: Lazy<Mutex<Option<Lockfile>>> =
-    Lazy::new(|| Mutex::new(Lockfile::create(lockfile_path()).ok()));
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Output {
+    /// Rename the workspaces in the window manager
+    Rename,
+    /// Print a JSON object mapping each workspace number to its icons on stdout, e.g. for a
+    /// waybar custom module. The workspaces aren't renamed
+    Json,
+}
 
-fn pretty_window(config: &Config, window: &Window) -> String {
-    for (name, icon) in &config.mappings {
-        if window.matches(name) {
-            return icon.clone();
-        }
-    }
-    error!("Couldn't identify window: {window:?}");
-    info!("Make sure to add an icon for this file in your config file!");
-    config.fallback_icon().into()
+/// The lock making sure a single instance runs at once, unless `--no-lock` is passed
+static LOCK: Mutex<Option<Lockfile>> = Mutex::new(None);
+
+/// How long we give the main loop to stop once we've been asked to exit, before exiting anyway.
+/// This leaves plenty of time to give the workspaces their plain number back
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn lockfile_path() -> PathBuf {
+    runtime_file("workstyle", "lock")
+}
+
+/// How often we refresh the lockfile, so watchdogs can tell from its mtime that we're not hung
+const LOCK_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Record our PID and the current time in the lockfile. The new contents are written aside then
+/// moved in place, so another instance checking the lock never reads a truncated file
+fn write_heartbeat(path: &Path) -> std::io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let tmp = path.with_extension("lock.tmp");
+    fs::write(&tmp, format!("{}\n{now}\n", process::id()))?;
+    fs::rename(&tmp, path)
 }
 
-fn pretty_windows(config: &Config, windows: &[Window]) -> String {
-    let mut s = String::new();
-    if config.other.deduplicate_icons {
-        let mut set = HashSet::new();
-        for window in windows {
-            let icon = pretty_window(config, window);
-            if set.get(&icon).is_none() {
-                s.push_str(&icon);
-                s.push(' ');
-                set.insert(icon);
+/// Create the lockfile and record our PID in it.
+/// If the lockfile was left behind by a process that isn't running anymore, reclaim it.
+fn create_lock() -> Option<Lockfile> {
+    let path = lockfile_path();
+    let lock = match Lockfile::create(&path) {
+        Ok(lock) => lock,
+        Err(_) => {
+            if let Some(pid) = lock_holder(&path) {
+                error!("The lock at {path:?} is held by running process: {pid}");
+                return None;
             }
+            warn!("Reclaiming stale lock: {path:?}");
+            fs::remove_file(&path).ok()?;
+            Lockfile::create(&path).ok()?
         }
-    } else {
-        for window in windows {
-            s.push_str(&pretty_window(config, window));
-            s.push(' ');
-        }
+    };
+    if let Err(e) = write_heartbeat(&path) {
+        warn!("Failed to record our PID in the lock: {e}");
     }
-    s
+    Some(lock)
 }
 
-fn lockfile_path() -> PathBuf {
-    let mut lockfile_path = match dirs::runtime_dir() {
-        Some(path) => path,
-        None => PathBuf::from("/tmp"),
-    };
-    lockfile_path.push("workstyle.lock");
-    lockfile_path
+/// The PID recorded in the lockfile, if that process is a running instance of workstyle
+fn lock_holder(path: &Path) -> Option<u32> {
+    let mut contents = fs::read_to_string(path).ok()?;
+    // The lockfile is empty from its creation until its holder records its PID in it
+    for _ in 0..10 {
+        if !contents.is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(50));
+        contents = fs::read_to_string(path).ok()?;
+    }
+    let pid: u32 = contents.lines().next()?.parse().ok()?;
+    let comm = fs::read(format!("/proc/{pid}/comm")).ok()?;
+    // The kernel truncates the name of the executable to 15 bytes there
+    let exe = env::current_exe().ok()?;
+    let name = exe.file_name()?.as_bytes();
+    (comm.strip_suffix(b"\n")? == &name[..name.len().min(15)]).then_some(pid)
 }
 
 fn aquire_lock() {
     // Try to aquire the lock
-    if LOCK.lock().unwrap().is_none() {
+    let lock = create_lock();
+    if lock.is_none() {
         error!("Failed to aquire the lock");
         exit(1);
     }
+    *LOCK.lock().unwrap() = lock;
 
-    // Drop the lock on exit
+    // Keep the heartbeat going for as long as we hold the lock
+    spawn(|| {
+        let path = lockfile_path();
+        loop {
+            sleep(LOCK_HEARTBEAT_INTERVAL);
+            // Hold the lock while writing, so we don't recreate the lockfile after it's dropped
+            let lock = LOCK.lock().unwrap();
+            if lock.is_none() {
+                break;
+            }
+            if let Err(e) = write_heartbeat(&path) {
+                warn!("Failed to refresh the lock: {e}");
+            }
+        }
+    });
+}
+
+/// Clean up when we're killed or when we panic
+fn clean_up_on_exit() {
+    // Ask the main loop to stop, so it restores the names of the workspaces and drops the lock
+    // before exiting. If it doesn't stop in time, e.g. as it is stuck connecting, exit anyway
     let mut signals = Signals::new([SIGTERM, SIGQUIT, SIGINT, SIGHUP])
         .expect("Failed to create signals iterator");
     spawn(move || {
         let _ = signals.forever().next();
-        drop(LOCK.lock().unwrap().take());
+        info!("Shutting down");
+        request_shutdown();
+        sleep(SHUTDOWN_TIMEOUT);
+        clean_up();
         exit(0);
     });
 
+    // Log the match stats on demand
+    let mut signals = Signals::new([SIGUSR2]).expect("Failed to create signals iterator");
+    spawn(move || {
+        for _ in signals.forever() {
+            log_match_stats();
+        }
+    });
+
     // Drop the lock on panic
     std::panic::set_hook(Box::new(|info| {
         error!("{info}");
         if let Ok(mut lock) = LOCK.lock() {
             drop(lock.take());
         }
+        status::remove();
     }));
 }
 
-fn run() -> Result<()> {
-    let args = Args::parse();
-    let mut wm = WindowManager::connect(args.enforce_window_manager)?;
-    info!("Successfully connected to WM");
+/// Release the lock and remove the status socket, before exiting
+fn clean_up() {
+    drop(LOCK.lock().unwrap().take());
+    status::remove();
+}
+
+/// Run the user's hook in the background, with the renamed workspace in its environment
+fn run_rename_hook(command: &str, workspace: &str, name: &str) {
+    match process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("WORKSTYLE_WORKSPACE", workspace)
+        .env("WORKSTYLE_NAME", name)
+        .spawn()
+    {
+        Ok(mut child) => {
+            // Reap the child without blocking the main loop
+            spawn(move || child.wait());
+        }
+        Err(e) => error!("Failed to run on_rename hook: \"{command}\": {e}"),
+    }
+}
+
+/// A workspace name we gave, as persisted across restarts with `persist_names`
+#[derive(Debug, Deserialize, Serialize)]
+struct PersistedName {
+    name: String,
+    /// Fingerprint of the windows the workspace held, so the name isn't restored once they
+    /// changed
+    windows: u64,
+}
+
+fn persisted_names_path() -> PathBuf {
+    runtime_file("workstyle-names", "json")
+}
+
+/// A hash of the windows of a workspace, which tells whether it still holds the same ones
+fn windows_fingerprint(windows: &[Window]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    windows
+        .iter()
+        .map(|window| &window.id)
+        .collect::<Vec<_>>()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Give the workspaces the names we persisted before restarting, provided they still hold the
+/// same windows, so they don't show up without icons until the first renames
+fn restore_names(config: &Config, wm: &mut WindowManager) -> Result<()> {
+    let path = persisted_names_path();
+    let persisted: HashMap<String, PersistedName> = match fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid persisted names: {path:?}"))?,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {path:?}")),
+    };
+    let renames: Vec<_> = wm
+        .get_windows_in_each_workspace(config)?
+        .into_iter()
+        .filter_map(|(name, windows)| {
+            let persisted = persisted.get(workspace_number_in(config, &name))?;
+            (persisted.windows == windows_fingerprint(&windows) && persisted.name != name)
+                .then(|| (name, persisted.name.clone()))
+        })
+        .collect();
+    debug!("Restoring {} persisted workspace names", renames.len());
+    wm.rename_workspaces(&renames)
+}
+
+fn run(args: &Args, mut wm: Box<WindowManager>) -> Result<()> {
+    info!("Successfully connected to {}", wm.name());
+    // Let systemd know we're up when running as a notify service. This is a no-op otherwise
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]) {
+        warn!("Failed to notify systemd of readiness: {e}");
+    }
+    // The names we last gave to each workspace, so we only rename workspaces when their name
+    // actually changes
+    let mut last_names: HashMap<String, String> = HashMap::new();
+    // The last JSON object we printed, when not renaming
+    let mut last_json: Option<String> = None;
+    // What the last event changed
+    let mut change = Change::Unknown;
+    // The fingerprint of the last pass, to skip the passes that would compute the same names
+    let mut last_fingerprint = None;
+    // The hash of the config files the workspaces were last renamed with, for `partial_updates`
+    let mut last_source_hash = None;
+    // When we last renamed workspaces, for `min_rename_interval_ms`
+    let mut last_rename: Option<Instant> = None;
+    // What we remember from the names we gave
+    let mut state = NamingState::default();
+    // Problems with the config are reported in the loop
+    if let Some(config) = Config::new()
+        .ok()
+        .filter(|config| config.other.persist_names)
+    {
+        if let Err(e) = restore_names(&config, &mut wm) {
+            warn!("Failed to restore the persisted workspace names: {e:#}");
+        }
+    }
 
     loop {
         // TODO: watch for changes using inotify and read the config only when needed
-        let config = Config::new()?;
-        let sep: &str = config.separator();
-
-        let workspaces = wm.get_windows_in_each_workspace()?;
+        let mut config = Config::new()?;
+        config.apply_theme(args.theme.as_deref())?;
+        config.apply_backend(wm.name())?;
+        if change == Change::Shutdown {
+            if args.output == Output::Rename {
+                restore_numbers(&config, &state, &mut wm)?;
+            }
+            return Ok(());
+        }
+        if config.other.check_fonts {
+            static CHECK_FONTS: Once = Once::new();
+            CHECK_FONTS.call_once(|| {
+                let config = config.clone();
+                // Don't delay the first renames
+                spawn(move || fonts::check_fonts(&config));
+            });
+        }
+        let workspaces = wm.get_windows_in_each_workspace(&config)?;
+        let focused = focused_workspace(&config, &mut wm)?;
+        // Most events, e.g. focus changes, don't change anything the names depend on
+        let fingerprint = fingerprint(&config, &workspaces, focused.as_deref());
+        if last_fingerprint == Some(fingerprint) {
+            // The windows with the unknown icon still have it
+            count_pass(&config);
+            change = wm.wait_for_event(&config)?;
+            continue;
+        }
+        if let (Some(interval), Some(last_rename)) = (config.min_rename_interval(), last_rename) {
+            let elapsed = last_rename.elapsed();
+            if elapsed < interval {
+                // Hold the changes back until then, then start over from the state at that
+                // point, which also covers everything that changed in the meantime
+                sleep(interval - elapsed);
+                change = Change::Unknown;
+                continue;
+            }
+        }
+        last_fingerprint = Some(fingerprint);
+        if config.other.sticky_icons || config.other.notify_unknown {
+            state.forget_closed_windows(&workspaces);
+        }
+        let Names {
+            names: mut new_names,
+            mut by_number,
+            icons,
+            effects,
+        } = name_workspaces(&config, &state, &workspaces, focused.as_deref());
+        effects.apply(&config, &mut state);
+        count_pass(&config);
+        if args.output == Output::Json {
+            let json = serde_json::to_string(&icons)?;
+            if last_json.as_ref() != Some(&json) {
+                println!("{json}");
+                stdout().flush()?;
+                last_json = Some(json);
+            }
+            change = wm.wait_for_event(&config)?;
+            continue;
+        }
+        let touched = touched_workspace(&config, &workspaces, &change, last_source_hash);
+        last_source_hash = Some(config.source_hash());
+        let mut names = HashMap::new();
+        let mut renames = Vec::new();
+        // The on_rename hooks to run once the workspaces are renamed
+        let mut hooks = Vec::new();
+        // The new name of each workspace, by number, for `persist_names`
+        let mut persisted = HashMap::new();
         for (name, windows) in workspaces {
-            let new_name = pretty_windows(&config, &windows);
-            let num = name
-                .split(sep)
-                .next()
-                .context("Unexpected workspace name")?;
-            if new_name.is_empty() {
-                wm.rename_workspace(&name, num)?;
+            // The scratchpad can't be renamed, it is only ever shown in the JSON output
+            let new_name = match new_names.remove(&name) {
+                Some(new_name) => new_name,
+                None => continue,
+            };
+            let num = workspace_number_in(&config, &name);
+            let workspace_config = config.for_workspace(num);
+            let new_name = if touched.as_ref().map_or(false, |touched| *touched != name) {
+                // Left as is by the last event
+                name.clone()
             } else {
-                wm.rename_workspace(&name, &format!("{num}{sep}{new_name}"))?;
+                new_name
+            };
+            let num = pad_number(num, workspace_config.other.number_width);
+            if new_name.trim().is_empty() {
+                // Sway rejects these, and so would most bars
+                warn!("Not renaming workspace: \"{name}\" as its new name would be empty");
+                by_number.remove(&num);
+                continue;
+            }
+            // The status socket shows the names the workspaces are left with
+            by_number.insert(num.clone(), new_name.clone());
+            if config.other.persist_names {
+                persisted.insert(
+                    workspace_number_in(&config, &name).to_string(),
+                    PersistedName {
+                        name: new_name.clone(),
+                        windows: windows_fingerprint(&windows),
+                    },
+                );
+            }
+            if new_name != name && last_names.get(&name) != Some(&new_name) {
+                if let Some(command) = &workspace_config.other.on_rename {
+                    hooks.push((command.clone(), num, new_name.clone()));
+                }
+                renames.push((name.clone(), new_name.clone()));
+            }
+            names.insert(name, new_name);
+        }
+        wm.rename_workspaces(&renames)?;
+        if !renames.is_empty() {
+            last_rename = Some(Instant::now());
+            if config.other.persist_names {
+                let path = persisted_names_path();
+                if let Err(e) = fs::write(&path, serde_json::to_string(&persisted)?) {
+                    warn!("Failed to persist the workspace names to {path:?}: {e}");
+                }
+            }
+        }
+        for (command, num, new_name) in hooks {
+            run_rename_hook(&command, &num, &new_name);
+        }
+        last_names = names;
+        if let Some(path) = &config.other.status_socket {
+            if let Err(e) = status::publish(path, serde_json::to_string(&by_number)?) {
+                error!("{e:#}");
             }
         }
 
-        wm.wait_for_event()?;
+        change = wm.wait_for_event(&config)?;
     }
 }
 
+/// Give the workspaces their plain number back, as they were before we renamed them, keeping
+/// their label with `keep_label`
+fn restore_numbers(config: &Config, state: &NamingState, wm: &mut WindowManager) -> Result<()> {
+    let renames: Vec<_> = wm
+        .get_windows_in_each_workspace(config)?
+        .into_keys()
+        .filter(|name| name != SCRATCHPAD)
+        .filter_map(|name| {
+            let original = original_name(config, state, &name);
+            (original != name).then(|| (name, original))
+        })
+        .collect();
+    info!("Restoring the names of {} workspaces", renames.len());
+    wm.rename_workspaces(&renames)
+}
+
+/// The focused workspace, when the names depend on it
+fn focused_workspace(config: &Config, wm: &mut WindowManager) -> Result<Option<String>> {
+    match config.other.focused_empty_icon {
+        Some(_) => wm.focused_workspace(),
+        None => Ok(None),
+    }
+}
+
+/// Print what each workspace would be renamed to, without renaming anything, and with `--explain`
+/// how each window got its icon
+fn print_status(args: &Args) -> Result<()> {
+    let mut wm = connect(args)?;
+    let mut config = Config::new()?;
+    config.apply_theme(args.theme.as_deref())?;
+    config.apply_backend(wm.name())?;
+    let focused = focused_workspace(&config, &mut wm)?;
+    let workspaces = wm.get_windows_in_each_workspace(&config)?;
+    let mut state = NamingState::default();
+    let Names {
+        names: new_names,
+        effects,
+        ..
+    } = name_workspaces(&config, &state, &workspaces, focused.as_deref());
+    for (name, windows) in &workspaces {
+        if let Some(new_name) = new_names.get(name) {
+            println!("{name} -> {new_name}");
+            if args.explain {
+                let workspace_config = config.for_workspace(workspace_number_in(&config, name));
+                for window in windows {
+                    let (_, icon, reason) = match_window(&workspace_config, &state, window);
+                    println!(
+                        "    {} -> \"{icon}\": {reason}",
+                        window
+                            .fields()
+                            .map(|field| format!("\"{field}\""))
+                            .join(", ")
+                    );
+                }
+            }
+        }
+    }
+    effects.apply(&config, &mut state);
+    Ok(())
+}
+
+/// Exit codes of `--config-check`. 4 is reserved for invalid patterns, should patterns ever be
+/// compiled, e.g. as regular expressions
+const CONFIG_OK: i32 = 0;
+const CONFIG_PARSE_ERROR: i32 = 2;
+const CONFIG_SEPARATOR_CONFLICT: i32 = 3;
+const CONFIG_MISSING: i32 = 5;
+
+/// Check the config file for `--config-check`, returning the exit code
+fn check_config(args: &Args) -> i32 {
+    let path = Config::path()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    // Unlike running, checking doesn't write the default config when there is none
+    let config = Config::load().and_then(|config| {
+        config
+            .map(|mut config| {
+                config.apply_theme(args.theme.as_deref())?;
+                Ok(config)
+            })
+            .transpose()
+    });
+    match config {
+        Err(e) => {
+            println!("parse-error {path}");
+            println!("{e:#}");
+            CONFIG_PARSE_ERROR
+        }
+        Ok(None) => {
+            println!("missing {path}");
+            println!("There is no config file, so the default config would be written there");
+            CONFIG_MISSING
+        }
+        Ok(Some(config)) => match config.separator_conflict() {
+            Some(conflict) => {
+                println!("separator-conflict {path}");
+                println!("{conflict}");
+                CONFIG_SEPARATOR_CONFLICT
+            }
+            None => {
+                println!("ok {path}");
+                CONFIG_OK
+            }
+        },
+    }
+}
+
+fn dump_tree(args: &Args) -> Result<()> {
+    let tree = connect(args)?.dump_tree()?;
+    println!("{tree:#}");
+    Ok(())
+}
+
+/// The `[other]` settings we need before connecting to the window manager. Problems with the
+/// config are reported by `run`
+fn read_other() -> Option<Other> {
+    let path = Config::path().ok()?;
+    let config = match config::stdin_config() {
+        Some(contents) => config::parse_toml(contents, &path),
+        None if path.exists() => config::parse_toml(&fs::read_to_string(&path).ok()?, &path),
+        None => return None,
+    };
+    let mut config = config.ok()?;
+    config.expand_env_vars().ok()?;
+    Some(config.other)
+}
+
+/// Connect to the window manager from the command line. Otherwise, to the first one we find,
+/// trying them in the order of `wm_priority` if it is set
+fn connect(args: &Args) -> Result<Box<WindowManager>> {
+    let socket = args.socket.as_deref();
+    let priority = read_other().and_then(|other| other.wm_priority);
+    match (args.enforce_window_manager, priority) {
+        (None, Some(priority)) => {
+            for wm in &priority {
+                match WindowManager::connect(Some(*wm), socket) {
+                    Ok(wm) => return Ok(wm),
+                    Err(e) => debug!("Failed to connect to {wm:?}: {e:#}"),
+                }
+            }
+            bail!("Couldn't connect to any of the window managers in wm_priority: {priority:?}")
+        }
+        (enforce, _) => WindowManager::connect(enforce, socket),
+    }
+}
+
+/// Log to stderr, or to the log file from the config. RUST_LOG takes precedence over `-v`, which
+/// takes precedence over the log level from the config
+fn init_logging(verbose: u8) {
+    let mut builder = env_logger::Builder::new();
+    let other = read_other();
+    // Reported once the logger is set up
+    let mut problems = Vec::new();
+    if let Some(other) = other {
+        if let Some(level) = &other.log_level {
+            match level.parse::<LevelFilter>() {
+                Ok(level) => {
+                    builder.filter_level(level);
+                }
+                Err(_) => problems.push(format!("Invalid log_level: \"{level}\"")),
+            }
+        }
+        if let Some(path) = &other.log_file {
+            match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(file) => {
+                    builder.target(Target::Pipe(Box::new(file)));
+                }
+                Err(e) => problems.push(format!("Failed to open log file: {path:?}: {e}")),
+            }
+        }
+    }
+    match verbose {
+        0 => {}
+        1 => {
+            builder.filter_level(LevelFilter::Info);
+        }
+        2 => {
+            builder.filter_level(LevelFilter::Debug);
+        }
+        _ => {
+            builder.filter_level(LevelFilter::Trace);
+        }
+    }
+    builder.parse_default_env().init();
+    for problem in problems {
+        error!("{problem}");
+    }
+}
+
+/// Parse the command line arguments, mentioning where the lockfile is in the help
+fn parse_args() -> Args {
+    let command = Args::command().after_help(format!(
+        "The lockfile, which holds our PID and is refreshed every {} seconds, is at: {}",
+        LOCK_HEARTBEAT_INTERVAL.as_secs(),
+        lockfile_path().display()
+    ));
+    Args::from_arg_matches(&command.get_matches()).unwrap_or_else(|e| e.exit())
+}
+
 fn main() {
-    env_logger::init();
-    let _ = Args::parse();
-    aquire_lock();
+    let args = parse_args();
+    if let Some(path) = &args.config {
+        if let Err(e) = config::override_path(path.clone()) {
+            eprintln!("{e:#}");
+            exit(1);
+        }
+    }
+    init_logging(args.verbose);
+    if let Some(Command::Migrate) = args.command {
+        match Config::migrate() {
+            Ok((path, count)) => println!("Converted {count} mappings to {path:?}"),
+            Err(e) => {
+                eprintln!("{e:#}");
+                exit(1);
+            }
+        }
+        return;
+    }
+    if args.config_check {
+        exit(check_config(&args));
+    }
+    if args.dump_tree {
+        if let Err(e) = dump_tree(&args) {
+            eprintln!("{e:#}");
+            exit(1);
+        }
+        return;
+    }
+    if args.status || args.explain {
+        if let Err(e) = print_status(&args) {
+            eprintln!("{e:#}");
+            exit(1);
+        }
+        return;
+    }
+    if args.which_wm {
+        match connect(&args) {
+            Ok(wm) => println!("{}", wm.name()),
+            Err(e) => {
+                eprintln!("{e:#}");
+                exit(1);
+            }
+        }
+        return;
+    }
+    // Other problems with the config are reported by the loop, which retries until they're
+    // fixed
+    if let Ok(config) = Config::new() {
+        if let Err(e) = config.check_mappings() {
+            error!("{e:#}");
+            exit(1);
+        }
+    }
+    if !args.no_lock {
+        aquire_lock();
+    }
+    clean_up_on_exit();
+    set_learn(args.learn);
+    let mut failed_attempts = 0;
     loop {
-        if let Err(e) = run() {
+        // Only failing to connect counts towards giving up, not losing a working connection
+        let result = match connect(&args) {
+            Ok(wm) => {
+                failed_attempts = 0;
+                run(&args, wm)
+            }
+            Err(e) => {
+                failed_attempts += 1;
+                Err(e)
+            }
+        };
+        if shutdown_requested() {
+            // The window manager was dropped on the way out of `run`
+            clean_up();
+            return;
+        }
+        if let Err(e) = result {
             error!("{e:#}");
+            if args.max_connect_attempts != 0 && failed_attempts >= args.max_connect_attempts {
+                error!("Giving up after {failed_attempts} failed attempts to connect to the WM");
+                clean_up();
+                exit(1);
+            }
             info!("Attempting to reconnect to the WM in 1 second");
             sleep(Duration::from_secs(1));
         }
@@ -0,0 +1,85 @@
+//! Streams the names of the workspaces to the clients of a unix socket, as line delimited json
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::spawn;
+use std::time::Duration;
+
+/// How long a client has to read a line before it gets disconnected, so a stuck client can't
+/// block the renames
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+struct Status {
+    /// Path of the socket, once we listen on it
+    path: Option<PathBuf>,
+    clients: Vec<UnixStream>,
+    /// Last published line, sent to the clients as soon as they connect
+    last: Option<String>,
+}
+
+static STATUS: Lazy<Mutex<Status>> = Lazy::new(|| Mutex::new(Status::default()));
+
+/// Send this line to all the clients of the socket, if it changed since the last time.
+/// Starts listening on the socket the first time
+pub fn publish(path: &Path, line: String) -> Result<()> {
+    let mut status = STATUS.lock().unwrap();
+    if status.path.is_none() {
+        listen(path)?;
+        status.path = Some(path.to_path_buf());
+    }
+    if status.last.as_ref() == Some(&line) {
+        return Ok(());
+    }
+    status
+        .clients
+        .retain_mut(|client| writeln!(client, "{line}").is_ok());
+    status.last = Some(line);
+    Ok(())
+}
+
+fn listen(path: &Path) -> Result<()> {
+    // The lock guarantees a socket left at this path isn't used by another instance
+    if fs::metadata(path).map_or(false, |metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path).context("Failed to remove stale status socket")?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to listen on status socket: {path:?}"))?;
+    spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept status socket client: {e}");
+                    continue;
+                }
+            };
+            if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+                continue;
+            }
+            let mut status = STATUS.lock().unwrap();
+            if let Some(last) = &status.last {
+                if writeln!(stream, "{last}").is_err() {
+                    continue;
+                }
+            }
+            status.clients.push(stream);
+        }
+    });
+    Ok(())
+}
+
+/// Remove the socket, if we listen on one
+pub fn remove() {
+    if let Ok(status) = STATUS.lock() {
+        if let Some(path) = &status.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::config::{Config, DedupCountStyle};
+use crate::window_manager::Window;
+use crate::{pretty_windows, with_count, Naming};
+
+const CONFIG_DEDUPLICATE: &str = "
+\"firefox\" = \"F\"
+\"kitty\" = \"K\"
+
+[other]
+deduplicate_icons = true
+";
+
+fn windows(app_ids: &[&str]) -> Vec<Window> {
+    app_ids
+        .iter()
+        .map(|app_id| Window {
+            app_id: Some(app_id.to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[test]
+fn test_first_occurrence_is_kept() {
+    let c = Config::from_str(CONFIG_DEDUPLICATE).unwrap();
+    let w = windows(&["kitty", "firefox", "kitty", "firefox"]);
+    assert_eq!("K F ", pretty_windows(&c, &w, &mut Naming::default()));
+}
+
+#[test]
+fn test_deduplication_follows_icon_order() {
+    let c = Config::from_str(&format!("{CONFIG_DEDUPLICATE}icon_order = \"config\"")).unwrap();
+    let w = windows(&["kitty", "firefox", "kitty"]);
+    assert_eq!("F K ", pretty_windows(&c, &w, &mut Naming::default()));
+}
+
+#[test]
+fn test_deduplicate_by_app() {
+    let c = Config::from_str(
+        "
+\"kitty\" = \"T\"
+\"alacritty\" = \"T\"
+
+[other]
+deduplicate_icons = true
+dedup_by = \"app\"
+",
+    )
+    .unwrap();
+    let w = windows(&["kitty", "alacritty", "kitty"]);
+    assert_eq!("T T ", pretty_windows(&c, &w, &mut Naming::default()));
+}
+
+#[test]
+fn test_superscript_count() {
+    assert_eq!("🦊", with_count("🦊", 1, DedupCountStyle::Superscript));
+    assert_eq!("🦊²", with_count("🦊", 2, DedupCountStyle::Superscript));
+    assert_eq!("🦊¹²", with_count("🦊", 12, DedupCountStyle::Superscript));
+}
+
+#[test]
+fn test_prefix_count() {
+    assert_eq!("🦊", with_count("🦊", 1, DedupCountStyle::Prefix));
+    assert_eq!("2×🦊", with_count("🦊", 2, DedupCountStyle::Prefix));
+    assert_eq!("12×🦊", with_count("🦊", 12, DedupCountStyle::Prefix));
+}
+
+#[test]
+fn test_counts_in_pretty_windows() {
+    let c = Config::from_str(&format!(
+        "{CONFIG_DEDUPLICATE}dedup_count_style = \"superscript\""
+    ))
+    .unwrap();
+    let w = windows(&["kitty", "firefox", "kitty"]);
+    assert_eq!("K² F ", pretty_windows(&c, &w, &mut Naming::default()));
+}
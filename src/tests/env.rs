@@ -0,0 +1,53 @@
+use crate::config::{expand_env, Config};
+use std::path::PathBuf;
+
+#[test]
+fn test_expand_env() {
+    std::env::set_var("WORKSTYLE_TEST_ICON", "🦊");
+    assert_eq!("🦊", expand_env("$WORKSTYLE_TEST_ICON").unwrap());
+    assert_eq!("<🦊>", expand_env("<${WORKSTYLE_TEST_ICON}>").unwrap());
+    assert_eq!("$5 $", expand_env("$$5 $").unwrap());
+}
+
+#[test]
+fn test_literal_dollar_in_icons() {
+    std::env::set_var("WORKSTYLE_TEST_ICON", "🦊");
+    let mut c = Config::from_str(
+        "
+\"price\" = \"$5\"
+\"money\" = \"$ \"
+\"shell\" = \"$$WORKSTYLE_TEST_ICON\"
+\"prompt\" = \"$#\"
+",
+    )
+    .unwrap();
+    c.expand_env_vars().unwrap();
+    let icons: Vec<_> = c.rules.iter().map(|rule| rule.icon.as_str()).collect();
+    assert_eq!(vec!["$5", "$ ", "$WORKSTYLE_TEST_ICON", "$#"], icons);
+}
+
+#[test]
+fn test_unset_variable_is_an_error() {
+    std::env::remove_var("WORKSTYLE_TEST_UNSET");
+    let mut c = Config::from_str("\"firefox\" = \"$WORKSTYLE_TEST_UNSET\"").unwrap();
+    let e = c.expand_env_vars().unwrap_err();
+    assert!(format!("{e:#}").contains("WORKSTYLE_TEST_UNSET"));
+}
+
+#[test]
+fn test_expand_env_in_paths() {
+    std::env::set_var("WORKSTYLE_TEST_DIR", "/run/user/1000");
+    let mut c = Config::from_str(
+        "[other]\nlog_file = \"$WORKSTYLE_TEST_DIR/workstyle.log\"\nstatus_socket = \"${WORKSTYLE_TEST_DIR}/workstyle.sock\"",
+    )
+    .unwrap();
+    c.expand_env_vars().unwrap();
+    assert_eq!(
+        Some(PathBuf::from("/run/user/1000/workstyle.log")),
+        c.other.log_file
+    );
+    assert_eq!(
+        Some(PathBuf::from("/run/user/1000/workstyle.sock")),
+        c.other.status_socket
+    );
+}
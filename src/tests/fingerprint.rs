@@ -0,0 +1,40 @@
+use crate::config::Config;
+use crate::fingerprint;
+use crate::window_manager::Window;
+use std::collections::BTreeMap;
+
+fn workspaces(focused: bool) -> BTreeMap<String, Vec<Window>> {
+    BTreeMap::from([(
+        "1".to_string(),
+        vec![Window {
+            app_id: Some("kitty".to_string()),
+            focused,
+            ..Default::default()
+        }],
+    )])
+}
+
+#[test]
+fn test_focus_changes_only_count_when_shown() {
+    let c = Config::from_str("\"kitty\" = \"K\"").unwrap();
+    assert_eq!(
+        fingerprint(&c, &workspaces(false), None),
+        fingerprint(&c, &workspaces(true), None)
+    );
+    let c = Config::from_str("\"kitty\" = \"K\"\n[other]\nfocused_wrap = \"[{icon}]\"").unwrap();
+    assert_ne!(
+        fingerprint(&c, &workspaces(false), None),
+        fingerprint(&c, &workspaces(true), None)
+    );
+}
+
+#[test]
+fn test_window_changes_count() {
+    let c = Config::from_str("\"kitty\" = \"K\"").unwrap();
+    let mut other = workspaces(false);
+    other.get_mut("1").unwrap()[0].name = Some("vim".to_string());
+    assert_ne!(
+        fingerprint(&c, &workspaces(false), None),
+        fingerprint(&c, &other, None)
+    );
+}
@@ -0,0 +1,31 @@
+use crate::config::{Config, HyprlandEvent};
+
+#[test]
+fn test_default_hyprland_events() {
+    let c = Config::from_str("").unwrap();
+    assert_eq!(&[HyprlandEvent::Window], c.hyprland_events());
+    // Focusing an empty workspace changes its icon
+    for config in [
+        "[other]\nfocused_empty_icon = \"o\"",
+        "[other]\nempty_icon = \"{num}\"",
+        "[workspace.3]\nempty_icon = \"3\"",
+    ] {
+        let c = Config::from_str(config).unwrap();
+        assert_eq!(
+            &[HyprlandEvent::Window, HyprlandEvent::Workspace],
+            c.hyprland_events()
+        );
+    }
+}
+
+#[test]
+fn test_configured_hyprland_events() {
+    let c = Config::from_str(
+        "[other]\nfocused_empty_icon = \"o\"\nhyprland_events = [\"window\", \"layer\"]",
+    )
+    .unwrap();
+    assert_eq!(
+        &[HyprlandEvent::Window, HyprlandEvent::Layer],
+        c.hyprland_events()
+    );
+}
@@ -0,0 +1,114 @@
+use crate::config::{duplicate_mappings, ignored_in_include, parse_with_base, Config};
+use crate::pretty_window;
+use crate::window_manager::Window;
+use std::fs;
+
+fn window(app_id: &str) -> Window {
+    Window {
+        app_id: Some(app_id.to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_include_precedence() {
+    let dir = std::env::temp_dir().join(format!("workstyle-include-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("first.toml"),
+        "\"firefox\" = \"1\"\n\"kitty\" = \"1\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("second.toml"),
+        "\"kitty\" = \"2\"\n\"code\" = \"2\"\n",
+    )
+    .unwrap();
+    let mut c = Config::from_str(
+        "
+include = [\"first.toml\", \"second.toml\"]
+\"code\" = \"main\"
+",
+    )
+    .unwrap();
+    c.merge_includes(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // Inline mappings win over includes
+    assert_eq!("main", pretty_window(&c, &window("code")));
+    // Later includes win over earlier ones
+    assert_eq!("2", pretty_window(&c, &window("kitty")));
+    assert_eq!("1", pretty_window(&c, &window("firefox")));
+}
+
+#[test]
+fn test_extends() {
+    let dir = std::env::temp_dir().join(format!("workstyle-extends-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("base.toml"),
+        "\"firefox\" = \"base\"\n\"kitty\" = \"base\"\n[other]\nfallback_icon = \"?\"\nseparator = \" | \"\n",
+    )
+    .unwrap();
+    let path = dir.join("config.toml");
+    let (c, _) = parse_with_base(
+        "extends = \"base.toml\"\n\"kitty\" = \"local\"\n[other]\nseparator = \": \"\n",
+        &path,
+        0,
+    )
+    .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // The local file wins conflicts
+    assert_eq!("local", pretty_window(&c, &window("kitty")));
+    assert_eq!(Some(": "), c.other.separator.as_deref());
+    // Anything else comes from the base
+    assert_eq!("base", pretty_window(&c, &window("firefox")));
+    assert_eq!("?", c.fallback_icon());
+}
+
+#[test]
+fn test_include_only_merges_rules() {
+    let dir = std::env::temp_dir().join(format!("workstyle-include-only-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let included = "
+\"kitty\" = \"K\"
+
+[other]
+fallback_icon = \"included\"
+
+[workspace.3]
+fallback_icon = \"3\"
+
+[theme.dark]
+\"kitty\" = \"D\"
+";
+    fs::write(dir.join("included.toml"), included).unwrap();
+    let mut c = Config::from_str("include = [\"included.toml\"]").unwrap();
+    c.merge_includes(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!("K", pretty_window(&c, &window("kitty")));
+    assert_eq!(None, c.other.fallback_icon);
+    assert!(c.workspaces.is_empty());
+    assert!(c.themes.is_empty());
+    // Which is reported
+    assert_eq!(
+        vec!["other", "workspace", "theme"],
+        ignored_in_include(included)
+    );
+    assert!(ignored_in_include("\"kitty\" = \"K\"").is_empty());
+}
+
+#[test]
+fn test_duplicate_mappings_across_includes() {
+    let earlier =
+        Config::from_str("\"firefox\" = \"1\"\n\"kitty\" = \"K\"\n\"code\" = \"C\"").unwrap();
+    let later = Config::from_str("\"firefox\" = \"2\"\n\"kitty\" = \"K\"").unwrap();
+    let duplicates = duplicate_mappings(&earlier.rules, &later.rules);
+    // Mapping kitty to the same icon twice is harmless
+    assert_eq!(1, duplicates.len());
+    let (earlier, later) = duplicates[0];
+    assert_eq!(vec!["firefox".to_string()], earlier.patterns);
+    assert_eq!(("1", "2"), (earlier.icon.as_str(), later.icon.as_str()));
+}
@@ -99,6 +99,7 @@ fn test_pretty_window() {
         name: Some("Icons Icon | Font Awesome - Chromium".to_string()),
         app_id: None,
         window_properties_class: Some("chromium".to_string()),
+        ..Default::default()
     };
     let c = Config::from_str(CONFIG_ISSUE_50).unwrap();
     assert_eq!("", pretty_window(&c, &w));
@@ -0,0 +1,27 @@
+use crate::window_manager::{KwinReports, KWIN_SCRIPT};
+use std::sync::mpsc::channel;
+use std::sync::Mutex;
+use zbus::Interface;
+
+/// The interface and the method the script calls with `callDBus`, which come right after the
+/// service and the path
+fn called_method() -> (String, String) {
+    let call = KWIN_SCRIPT.split("callDBus(").nth(1).unwrap();
+    let mut args = call
+        .split(',')
+        .map(|arg| arg.trim().trim_matches('"').to_string());
+    (args.nth(2).unwrap(), args.next().unwrap())
+}
+
+#[test]
+fn test_script_calls_the_report_method() {
+    let (interface, method) = called_method();
+    assert_eq!(KwinReports::name().as_str(), interface);
+    let (tx, _rx) = channel();
+    let mut introspection = String::new();
+    KwinReports(Mutex::new(tx)).introspect_to_writer(&mut introspection, 0);
+    assert!(
+        introspection.contains(&format!("<method name=\"{method}\">")),
+        "{method} isn't exported: {introspection}"
+    );
+}
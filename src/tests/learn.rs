@@ -0,0 +1,37 @@
+use crate::config::{with_suggested_mapping, Config};
+use crate::window_manager::Window;
+
+fn window(app_id: &str) -> Window {
+    Window {
+        name: Some("Files".to_string()),
+        app_id: Some(app_id.to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_suggestion_goes_before_first_table() {
+    let contents = "\"firefox\" = \"F\"\n\n[other]\nfallback_icon = \"?\"\n";
+    let learned = with_suggested_mapping(contents, &window("nautilus")).unwrap();
+    assert_eq!(
+        "\"firefox\" = \"F\"\n\n# \"nautilus\" = \"-\"\n[other]\nfallback_icon = \"?\"\n",
+        learned
+    );
+    // Still a simple mapping once uncommented
+    let c = Config::from_str(&learned.replace("# \"nautilus\"", "\"nautilus\"")).unwrap();
+    assert_eq!("?", c.fallback_icon());
+    assert_eq!(2, c.rules.len());
+}
+
+#[test]
+fn test_suggestion_is_appended_without_tables() {
+    let learned = with_suggested_mapping("\"firefox\" = \"F\"", &window("nautilus")).unwrap();
+    assert_eq!("\"firefox\" = \"F\"\n# \"nautilus\" = \"-\"\n", learned);
+}
+
+#[test]
+fn test_known_windows_are_not_suggested_twice() {
+    let contents = "\"firefox\" = \"F\"\n# \"nautilus\" = \"-\"\n";
+    assert_eq!(None, with_suggested_mapping(contents, &window("nautilus")));
+    assert_eq!(None, with_suggested_mapping(contents, &window("firefox")));
+}
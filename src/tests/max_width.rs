@@ -0,0 +1,41 @@
+use crate::config::Config;
+use crate::window_manager::Window;
+use crate::{icon_width, pretty_windows, Naming};
+
+fn window(app_id: &str) -> Window {
+    Window {
+        name: None,
+        app_id: Some(app_id.to_string()),
+        window_properties_class: None,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_icon_width() {
+    assert_eq!(1, icon_width("a"));
+    assert_eq!(2, icon_width("🦊"));
+    // Family: man, woman, girl joined with zero width joiners
+    assert_eq!(2, icon_width("👨\u{200d}👩\u{200d}👧"));
+    // Heart with emoji variation selector
+    assert_eq!(2, icon_width("❤\u{fe0f}"));
+}
+
+#[test]
+fn test_max_width_doesnt_split_composed_emoji() {
+    let c = Config::from_str(
+        "
+\"family\" = \"👨\u{200d}👩\u{200d}👧\"
+\"fox\" = \"🦊\"
+
+[other]
+max_width = 5
+",
+    )
+    .unwrap();
+    let windows = [window("family"), window("fox"), window("family")];
+    assert_eq!(
+        "👨\u{200d}👩\u{200d}👧 🦊 … ",
+        pretty_windows(&c, &windows, &mut Naming::default())
+    );
+}
@@ -0,0 +1,56 @@
+use crate::config::Config;
+
+#[test]
+fn test_to_toml_round_trip() {
+    let c = Config::from_str(
+        "
+\"firefox\" = \"F\"
+
+[[rule]]
+patterns = [\"code\", \"codium\"]
+icon = \"C\"
+
+[[rule]]
+patterns = [\"alacritty\"]
+icon = \"A\"
+note = \"The terminal\"
+
+[other]
+fallback_icon = \"?\"
+",
+    )
+    .unwrap();
+    let round_tripped = Config::from_str(&c.to_toml().unwrap()).unwrap();
+    assert_eq!(c.rules, round_tripped.rules);
+    assert_eq!(Some("The terminal"), round_tripped.rules[2].note.as_deref());
+    assert_eq!(Some("?"), round_tripped.other.fallback_icon.as_deref());
+}
+
+const LEGACY_CONFIG: &str = "
+firefox: F
+code: C
+other:
+  fallback_icon: \"?\"
+  deduplicate_icons: true
+";
+
+#[test]
+fn test_yaml_to_toml() {
+    let c = Config::from_yaml(LEGACY_CONFIG).unwrap();
+    let toml = c.to_toml().unwrap();
+    let migrated = Config::from_str(&toml).unwrap();
+    assert_eq!(c.rules, migrated.rules);
+    assert_eq!(2, migrated.rules.len());
+    assert_eq!(Some("?"), migrated.other.fallback_icon.as_deref());
+    assert!(migrated.other.deduplicate_icons);
+    // Only the settings from the legacy config are written
+    assert!(toml.contains("deduplicate_icons"));
+    assert!(!toml.contains("pango"));
+    assert!(!toml.contains("icon_order"));
+}
+
+#[test]
+fn test_to_toml_without_settings() {
+    let c = Config::from_str("\"firefox\" = \"F\"").unwrap();
+    assert_eq!("firefox = \"F\"\n", c.to_toml().unwrap());
+}
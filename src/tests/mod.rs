@@ -1,2 +1,38 @@
 #[cfg(test)]
+mod deduplicate;
+#[cfg(test)]
+mod env;
+#[cfg(test)]
+mod fingerprint;
+#[cfg(test)]
+mod hyprland;
+#[cfg(test)]
+mod include;
+#[cfg(test)]
 mod issue_50;
+#[cfg(test)]
+mod kwin;
+#[cfg(test)]
+mod learn;
+#[cfg(test)]
+mod max_width;
+#[cfg(test)]
+mod migrate;
+#[cfg(test)]
+mod pango;
+#[cfg(test)]
+mod parse_errors;
+#[cfg(test)]
+mod partial_updates;
+#[cfg(test)]
+mod rename_command;
+#[cfg(test)]
+mod rules;
+#[cfg(test)]
+mod separator;
+#[cfg(test)]
+mod themes;
+#[cfg(test)]
+mod workspace_number;
+#[cfg(test)]
+mod workspace_overrides;
@@ -0,0 +1,20 @@
+use crate::config::Config;
+
+const CONFIG_PANGO: &str = "
+\"firefox\" = \"<span color='#f60'>🦊</span>\"
+
+[other]
+separator = \"#\"
+";
+
+#[test]
+fn test_separator_in_markup_is_rejected_without_pango() {
+    let c = Config::from_str(CONFIG_PANGO).unwrap();
+    assert_eq!(": ", c.separator());
+}
+
+#[test]
+fn test_separator_in_markup_is_allowed_with_pango() {
+    let c = Config::from_str(&format!("{CONFIG_PANGO}pango = true")).unwrap();
+    assert_eq!("#", c.separator());
+}
@@ -0,0 +1,12 @@
+use crate::config::parse_toml;
+use std::path::Path;
+
+#[test]
+fn test_unknown_key_is_named_with_its_line() {
+    let contents = "\"firefox\" = \"F\"\n\n[other]\ndeduplcate_icons = true\n";
+    let e = parse_toml(contents, Path::new("config.toml")).unwrap_err();
+    let message = format!("{e:#}");
+    assert!(message.contains("config.toml"), "{message}");
+    assert!(message.contains("deduplcate_icons"), "{message}");
+    assert!(message.contains("line"), "{message}");
+}
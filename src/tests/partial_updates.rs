@@ -0,0 +1,48 @@
+use crate::config::{parse_with_base, Config};
+use crate::touched_workspace;
+use crate::window_manager::{Change, Window, WindowId};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The config loaded from a file with these contents, along with the hash of the file
+fn load(contents: &str) -> Config {
+    parse_with_base(contents, Path::new("config.toml"), 0)
+        .unwrap()
+        .0
+}
+
+fn workspaces() -> BTreeMap<String, Vec<Window>> {
+    BTreeMap::from([
+        (
+            "1".to_string(),
+            vec![Window {
+                id: Some(WindowId::Node(1)),
+                ..Default::default()
+            }],
+        ),
+        ("2".to_string(), vec![]),
+    ])
+}
+
+#[test]
+fn test_only_the_workspace_of_the_window_is_touched() {
+    let c = Config::from_str("[other]\npartial_updates = true").unwrap();
+    let change = Change::Window(WindowId::Node(1));
+    let touched = touched_workspace(&c, &workspaces(), &change, Some(c.source_hash()));
+    assert_eq!(Some("1".to_string()), touched);
+    let touched = touched_workspace(&c, &workspaces(), &Change::Unknown, Some(c.source_hash()));
+    assert_eq!(None, touched);
+}
+
+#[test]
+fn test_all_workspaces_are_touched_after_reloading_the_config() {
+    let old = load("\"kitty\" = \"K\"\n[other]\npartial_updates = true");
+    let c = load("\"kitty\" = \"k\"\n[other]\npartial_updates = true");
+    let change = Change::Window(WindowId::Node(1));
+    assert_eq!(
+        None,
+        touched_workspace(&c, &workspaces(), &change, Some(old.source_hash()))
+    );
+    // Nor is any workspace left out on the first renames
+    assert_eq!(None, touched_workspace(&c, &workspaces(), &change, None));
+}
@@ -0,0 +1,17 @@
+use crate::window_manager::rename_command;
+
+#[test]
+fn test_plain_names() {
+    assert_eq!(
+        "rename workspace \"1\" to \"1: 🦊 \"",
+        rename_command("1", "1: 🦊 ")
+    );
+}
+
+#[test]
+fn test_quotes_and_backslashes_are_escaped() {
+    assert_eq!(
+        r#"rename workspace "1" to "1: \" \\ ""#,
+        rename_command("1", r#"1: " \ "#)
+    );
+}
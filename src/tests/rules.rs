@@ -0,0 +1,510 @@
+use crate::config::Config;
+use crate::window_manager::Window;
+use crate::{match_window, pretty_window, pretty_windows, Naming, NamingState, Reason};
+
+const CONFIG_FIREFOX_UNLESS: &str = "
+[[rule]]
+patterns = [\"firefox\"]
+icon = \"F\"
+unless = \"private browsing\"
+
+[[rule]]
+patterns = [\"private browsing\"]
+icon = \"P\"
+";
+
+fn firefox(title: &str) -> Window {
+    Window {
+        name: Some(title.to_string()),
+        app_id: Some("firefox".to_string()),
+        window_properties_class: None,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_unless_excludes_matching_window() {
+    let c = Config::from_str(CONFIG_FIREFOX_UNLESS).unwrap();
+    assert_eq!(
+        "P",
+        pretty_window(&c, &firefox("Mozilla Firefox Private Browsing"))
+    );
+}
+
+#[test]
+fn test_unless_keeps_other_windows() {
+    let c = Config::from_str(CONFIG_FIREFOX_UNLESS).unwrap();
+    assert_eq!("F", pretty_window(&c, &firefox("Mozilla Firefox")));
+}
+
+#[test]
+fn test_priority_beats_file_order() {
+    let c = Config::from_str(
+        "
+\"code\" = \"C\"
+
+[[rule]]
+patterns = [\"visual studio code\"]
+icon = \"V\"
+priority = 1
+",
+    )
+    .unwrap();
+    let w = Window {
+        name: Some("main.rs - Visual Studio Code".to_string()),
+        app_id: Some("code".to_string()),
+        window_properties_class: None,
+        ..Default::default()
+    };
+    assert_eq!("V", pretty_window(&c, &w));
+}
+
+#[test]
+fn test_anchored_pattern() {
+    let c = Config::from_str(
+        "
+\"^code$\" = \"C\"
+\"^vs\" = \"V\"
+",
+    )
+    .unwrap();
+    let window = |app_id: &str| Window {
+        app_id: Some(app_id.to_string()),
+        ..Default::default()
+    };
+    assert_eq!("C", pretty_window(&c, &window("code")));
+    assert_eq!("V", pretty_window(&c, &window("vscode")));
+    assert_eq!(c.fallback_icon(), pretty_window(&c, &window("code-oss")));
+}
+
+#[test]
+fn test_empty_icon_hides_window() {
+    let c = Config::from_str(
+        "
+\"firefox\" = \"F\"
+\"kitty\" = \"K\"
+\"nm-applet\" = \"\"
+",
+    )
+    .unwrap();
+    let windows: Vec<_> = ["firefox", "nm-applet", "kitty"]
+        .into_iter()
+        .map(|app_id| Window {
+            app_id: Some(app_id.to_string()),
+            ..Default::default()
+        })
+        .collect();
+    assert_eq!("F K ", pretty_windows(&c, &windows, &mut Naming::default()));
+}
+
+#[test]
+fn test_max_icons_per_rule() {
+    let c = Config::from_str(
+        "
+\"firefox\" = \"F\"
+
+[[rule]]
+patterns = [\"kitty\"]
+icon = \"K\"
+max = 2
+",
+    )
+    .unwrap();
+    let windows: Vec<_> = ["kitty", "firefox", "kitty", "kitty", "firefox"]
+        .into_iter()
+        .map(|app_id| Window {
+            app_id: Some(app_id.to_string()),
+            ..Default::default()
+        })
+        .collect();
+    assert_eq!(
+        "K F K F ",
+        pretty_windows(&c, &windows, &mut Naming::default())
+    );
+}
+
+#[test]
+fn test_wm_class_instance() {
+    let c = Config::from_str("\"google-chrome\" = \"G\"").unwrap();
+    let w = Window {
+        window_properties_class: Some("Chromium-browser".to_string()),
+        window_properties_instance: Some("google-chrome".to_string()),
+        ..Default::default()
+    };
+    assert_eq!("G", pretty_window(&c, &w));
+}
+
+#[test]
+fn test_normalize_separators() {
+    let config = "\"google-chrome\" = \"G\"\n[other]\nfallback_icon = \"?\"\n";
+    let w = Window {
+        window_properties_class: Some("Google_chrome".to_string()),
+        ..Default::default()
+    };
+    let c = Config::from_str(config).unwrap();
+    assert_eq!("?", pretty_window(&c, &w));
+    let c = Config::from_str(&format!("{config}normalize_separators = true")).unwrap();
+    assert_eq!("G", pretty_window(&c, &w));
+}
+
+#[test]
+fn test_ignore_workspaces() {
+    let c = Config::from_str("[other]\nignore_workspaces = [\"^music$\", \"^1$\"]").unwrap();
+    assert!(c.ignores_workspace("Music"));
+    assert!(c.ignores_workspace("1"));
+    assert!(!c.ignores_workspace("10"));
+}
+
+#[test]
+fn test_icon_depends_on_window_count() {
+    let c = Config::from_str(
+        "
+[[rule]]
+patterns = [\"signal\"]
+icon = \"1\"
+if_count = [{ at_least = 3, icon = \"3\" }, { at_least = 2, icon = \"2\" }]
+",
+    )
+    .unwrap();
+    let signal = |count| -> Vec<_> {
+        (0..count)
+            .map(|_| Window {
+                app_id: Some("signal".to_string()),
+                ..Default::default()
+            })
+            .collect()
+    };
+    assert_eq!("1 ", pretty_windows(&c, &signal(1), &mut Naming::default()));
+    assert_eq!(
+        "2 2 ",
+        pretty_windows(&c, &signal(2), &mut Naming::default())
+    );
+    assert_eq!(
+        "3 3 3 ",
+        pretty_windows(&c, &signal(3), &mut Naming::default())
+    );
+}
+
+#[test]
+fn test_match_combined_fields() {
+    let config = "\"firefox youtube\" = \"Y\"\n\"firefox\" = \"F\"\n[other]\n";
+    let w = firefox("YouTube - Mozilla Firefox");
+    let c = Config::from_str(config).unwrap();
+    assert_eq!("F", pretty_window(&c, &w));
+    let c = Config::from_str(&format!("{config}match_combined = true")).unwrap();
+    assert_eq!("Y", pretty_window(&c, &w));
+}
+
+#[test]
+fn test_unknown_icon_depends_on_known_fields() {
+    let c = Config::from_str(
+        "
+\"firefox\" = \"F\"
+[other]
+unknown_icon = \"?\"
+untitled_icon = \"U\"
+no_app_icon = \"N\"
+",
+    )
+    .unwrap();
+    let window = |name: Option<&str>, app_id: Option<&str>| Window {
+        name: name.map(str::to_string),
+        app_id: app_id.map(str::to_string),
+        ..Default::default()
+    };
+    assert_eq!("?", pretty_window(&c, &window(Some("title"), Some("app"))));
+    assert_eq!("U", pretty_window(&c, &window(None, Some("app"))));
+    assert_eq!("N", pretty_window(&c, &window(Some("title"), None)));
+    assert_eq!("?", pretty_window(&c, &window(None, None)));
+}
+
+#[test]
+fn test_compose_matches() {
+    let config = "\"firefox\" = \"F\"\n\"github\" = \"G\"\n\"mozilla\" = \"F\"\n[other]\n";
+    let w = firefox("GitHub - Mozilla Firefox");
+    let c = Config::from_str(config).unwrap();
+    assert_eq!("F", pretty_window(&c, &w));
+    let c = Config::from_str(&format!("{config}compose_matches = true")).unwrap();
+    assert_eq!("FG", pretty_window(&c, &w));
+}
+
+#[test]
+fn test_exact_app_id_beats_earlier_title_match() {
+    let c = Config::from_str(
+        "
+\"kitty\" = \"K\"
+\"code\" = \"C\"
+",
+    )
+    .unwrap();
+    let w = Window {
+        name: Some("kitty.conf - Code".to_string()),
+        app_id: Some("code".to_string()),
+        ..Default::default()
+    };
+    assert_eq!("C", pretty_window(&c, &w));
+}
+
+#[test]
+fn test_mappings_differing_by_case_are_kept() {
+    let c =
+        Config::from_str("\"firefox\" = \"F\"\n\"Firefox\" = \"G\"\n\"kitty\" = \"K\"").unwrap();
+    assert_eq!(3, c.rules.len());
+    assert_eq!("F", pretty_window(&c, &firefox("Mozilla Firefox")));
+}
+
+#[test]
+fn test_fullscreen_rule_and_icon() {
+    let c = Config::from_str(
+        "
+[[rule]]
+patterns = [\"mpv\"]
+icon = \"M\"
+fullscreen = true
+
+[[rule]]
+patterns = [\"mpv\"]
+icon = \"m\"
+
+[other]
+fullscreen_icon = \"[]\"
+",
+    )
+    .unwrap();
+    let mpv = |fullscreen| Window {
+        app_id: Some("mpv".to_string()),
+        fullscreen,
+        ..Default::default()
+    };
+    assert_eq!(
+        "m ",
+        pretty_windows(&c, &[mpv(false)], &mut Naming::default())
+    );
+    assert_eq!(
+        "M [] ",
+        pretty_windows(&c, &[mpv(true)], &mut Naming::default())
+    );
+}
+
+#[test]
+fn test_rule_restricted_to_a_field() {
+    let c = Config::from_str(
+        "
+[[rule]]
+patterns = [\"firefox\"]
+icon = \"W\"
+field = \"app_id\"
+
+[[rule]]
+patterns = [\"firefox\"]
+icon = \"X\"
+",
+    )
+    .unwrap();
+    let xwayland = Window {
+        window_properties_class: Some("firefox".to_string()),
+        ..Default::default()
+    };
+    assert_eq!("W", pretty_window(&c, &firefox("Mozilla Firefox")));
+    assert_eq!("X", pretty_window(&c, &xwayland));
+}
+
+#[test]
+fn test_icon_padding() {
+    let c = Config::from_str(
+        "
+\"firefox\" = \"F\"
+\"kitty\" = \"K\"
+[other.icon_padding]
+\"F\" = { left = 1, right = 2 }
+",
+    )
+    .unwrap();
+    let windows: Vec<_> = ["firefox", "kitty"]
+        .into_iter()
+        .map(|app_id| Window {
+            app_id: Some(app_id.to_string()),
+            ..Default::default()
+        })
+        .collect();
+    assert_eq!(
+        " F   K ",
+        pretty_windows(&c, &windows, &mut Naming::default())
+    );
+}
+
+#[test]
+fn test_match_window_explains_the_matched_pattern() {
+    let c = Config::from_str(CONFIG_FIREFOX_UNLESS).unwrap();
+    let (position, icon, reason) = match_window(
+        &c,
+        &NamingState::default(),
+        &firefox("Mozilla Firefox Private Browsing"),
+    );
+    assert_eq!(Some(1), position);
+    assert_eq!("P", icon);
+    assert_eq!(Reason::Pattern("private browsing".to_string()), reason);
+    let kitty = Window {
+        app_id: Some("kitty".to_string()),
+        ..Default::default()
+    };
+    let (position, _, reason) = match_window(&c, &NamingState::default(), &kitty);
+    assert_eq!(None, position);
+    assert_eq!(Reason::Unknown, reason);
+}
+
+#[test]
+fn test_rule_matching_marks() {
+    let c = Config::from_str(
+        "
+[[rule]]
+patterns = [\"^music$\"]
+icon = \"M\"
+field = \"mark\"
+
+[[rule]]
+patterns = [\"music\"]
+icon = \"P\"
+",
+    )
+    .unwrap();
+    let mut w = Window {
+        app_id: Some("kitty".to_string()),
+        marks: vec!["chat".to_string(), "music".to_string()],
+        ..Default::default()
+    };
+    assert_eq!("M", pretty_window(&c, &w));
+    // Marks aren't matched by rules matching all the fields
+    w.marks = vec!["music player".to_string()];
+    assert_eq!(c.fallback_icon(), pretty_window(&c, &w));
+}
+
+#[test]
+fn test_focused_icon() {
+    let c = Config::from_str(
+        "
+[[rule]]
+patterns = [\"firefox\"]
+icon = \"o\"
+focused_icon = \"O\"
+
+[other]
+deduplicate_icons = true
+",
+    )
+    .unwrap();
+    let mut focused = firefox("Mozilla Firefox");
+    focused.focused = true;
+    assert_eq!(
+        "o ",
+        pretty_windows(&c, &[firefox("Mozilla Firefox")], &mut Naming::default())
+    );
+    assert_eq!("O ", pretty_windows(&c, &[focused], &mut Naming::default()));
+    let mut focused = firefox("Mozilla Firefox");
+    focused.focused = true;
+    // Deduplicated with the unfocused window, which doesn't lose the focus
+    assert_eq!(
+        "O ",
+        pretty_windows(
+            &c,
+            &[firefox("Mozilla Firefox"), focused],
+            &mut Naming::default()
+        )
+    );
+}
+
+#[test]
+fn test_match_priority() {
+    // "fox" doesn't name the app id exactly, so only the order of the rules and `match_priority`
+    // matter
+    let config = |priority| {
+        Config::from_str(&format!(
+            "
+\"notion\" = \"N\"
+\"fox\" = \"F\"
+
+[other]
+match_priority = {priority}
+"
+        ))
+        .unwrap()
+    };
+    let w = firefox("Notion - Mozilla Firefox");
+    assert_eq!("N", pretty_window(&config("[]"), &w));
+    assert_eq!("F", pretty_window(&config("[\"app_id\"]"), &w));
+    // Fields that aren't listed are still consulted last
+    assert_eq!(
+        "N",
+        pretty_window(&config("[\"window_properties_class\"]"), &w)
+    );
+}
+
+#[test]
+fn test_match_priority_over_exact_app_id() {
+    let config = |priority| {
+        Config::from_str(&format!(
+            "
+\"notion\" = \"N\"
+\"firefox\" = \"F\"
+
+[other]
+match_priority = {priority}
+"
+        ))
+        .unwrap()
+    };
+    let w = firefox("Notion - Mozilla Firefox");
+    assert_eq!("F", pretty_window(&config("[]"), &w));
+    // The title decides, even though a rule names the app id exactly
+    assert_eq!("N", pretty_window(&config("[\"name\", \"app_id\"]"), &w));
+    assert_eq!("F", pretty_window(&config("[\"app_id\", \"name\"]"), &w));
+    // Fields that match no rule don't stand in the way
+    assert_eq!(
+        "F",
+        pretty_window(&config("[\"window_properties_class\"]"), &w)
+    );
+}
+
+#[test]
+fn test_compact_runs() {
+    let c = Config::from_str("\"firefox\" = \"F\"\n\"code\" = \"C\"\n[other]\ncompact_runs = true")
+        .unwrap();
+    let code = Window {
+        app_id: Some("code".to_string()),
+        ..Default::default()
+    };
+    let windows = [firefox("a"), firefox("b"), code];
+    assert_eq!(
+        "F×2 C ",
+        pretty_windows(&c, &windows, &mut Naming::default())
+    );
+}
+
+#[test]
+fn test_compact_runs_keeps_icons_apart() {
+    let c = Config::from_str("\"firefox\" = \"F\"\n\"code\" = \"C\"\n[other]\ncompact_runs = true")
+        .unwrap();
+    let code = || Window {
+        app_id: Some("code".to_string()),
+        ..Default::default()
+    };
+    let windows = [firefox("a"), code(), firefox("b"), code(), code()];
+    assert_eq!(
+        "F C F C×2 ",
+        pretty_windows(&c, &windows, &mut Naming::default())
+    );
+}
+
+#[test]
+fn test_blank_windows_only_shown_with_blank_icon() {
+    let blank = Window::default();
+    let c = Config::from_str("\"firefox\" = \"F\"").unwrap();
+    assert!(!blank.is_shown(&c));
+    let c = Config::from_str("\"firefox\" = \"F\"\n[other]\nblank_icon = \"B\"").unwrap();
+    assert!(blank.is_shown(&c));
+    assert!(firefox("").is_shown(&c));
+    assert_eq!(
+        "B F ",
+        pretty_windows(&c, &[blank, firefox("")], &mut Naming::default())
+    );
+}
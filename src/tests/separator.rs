@@ -0,0 +1,56 @@
+use crate::config::{contains_graphemes, Config};
+
+#[test]
+fn test_separator_inside_keycap_emoji_is_allowed() {
+    let c =
+        Config::from_str("\"code\" = \"1\u{fe0f}\u{20e3}\"\n[other]\nseparator = \"1\"").unwrap();
+    assert_eq!("1", c.separator());
+}
+
+#[test]
+fn test_separator_inside_zwj_sequence_is_allowed() {
+    // 👩‍💻 is 👩, a zero width joiner and 💻, shown as a single glyph
+    let c = Config::from_str("\"code\" = \"👩\u{200d}💻\"\n[other]\nseparator = \"👩\"").unwrap();
+    assert_eq!("👩", c.separator());
+}
+
+#[test]
+fn test_separator_among_composed_emoji_is_rejected() {
+    let c = Config::from_str("\"code\" = \"👩\u{200d}💻|🦊\"\n[other]\nseparator = \"|\"").unwrap();
+    assert_eq!(": ", c.separator());
+}
+
+#[test]
+fn test_contains_graphemes() {
+    assert!(contains_graphemes("a: b", ": "));
+    assert!(!contains_graphemes("👩\u{200d}💻", "💻"));
+    assert!(contains_graphemes("👩 💻", "💻"));
+    assert!(!contains_graphemes("🦊", ""));
+}
+
+#[test]
+fn test_separator_conflict() {
+    let c = Config::from_str("\"code\" = \"a|b\"\n[other]\nseparator = \"|\"").unwrap();
+    assert!(c.separator_conflict().unwrap().contains("\"a|b\""));
+    let c = Config::from_str("\"code\" = \"a|b\"\n[other]\nseparator = \"\"").unwrap();
+    assert_eq!(None, c.separator_conflict());
+}
+
+#[test]
+fn test_empty_separator_with_digit_icons_is_rejected() {
+    let c = Config::from_str("\"code\" = \"2C\"\n[other]\nseparator = \"\"").unwrap();
+    assert!(c.separator_conflict().unwrap().contains("\"2C\""));
+    assert_eq!(": ", c.separator());
+    let c = Config::from_str("\"code\" = \"C\"\n[other]\nseparator = \"\"").unwrap();
+    assert_eq!("", c.separator());
+}
+
+#[test]
+fn test_empty_separator_with_prefix_counts_is_rejected() {
+    let c = Config::from_str(
+        "\"code\" = \"C\"\n[other]\nseparator = \"\"\ndeduplicate_icons = true\ndedup_count_style = \"prefix\"",
+    )
+    .unwrap();
+    assert!(c.separator_conflict().unwrap().contains("prefix"));
+    assert_eq!(": ", c.separator());
+}
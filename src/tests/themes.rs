@@ -0,0 +1,45 @@
+use crate::config::Config;
+use crate::pretty_window;
+use crate::window_manager::Window;
+
+const CONFIG_THEMES: &str = "
+\"firefox\" = \"F\"
+\"kitty\" = \"K\"
+
+[other]
+active_theme = \"emoji\"
+
+[theme.emoji]
+\"firefox\" = \"🦊\"
+
+[theme.nerd]
+\"firefox\" = \"N\"
+";
+
+fn window(app_id: &str) -> Window {
+    Window {
+        app_id: Some(app_id.to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_active_theme_before_global_rules() {
+    let mut c = Config::from_str(CONFIG_THEMES).unwrap();
+    c.apply_theme(None).unwrap();
+    assert_eq!("🦊", pretty_window(&c, &window("firefox")));
+    assert_eq!("K", pretty_window(&c, &window("kitty")));
+}
+
+#[test]
+fn test_theme_argument_beats_active_theme() {
+    let mut c = Config::from_str(CONFIG_THEMES).unwrap();
+    c.apply_theme(Some("nerd")).unwrap();
+    assert_eq!("N", pretty_window(&c, &window("firefox")));
+}
+
+#[test]
+fn test_unknown_theme() {
+    let mut c = Config::from_str(CONFIG_THEMES).unwrap();
+    assert!(c.apply_theme(Some("missing")).is_err());
+}
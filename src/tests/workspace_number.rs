@@ -0,0 +1,304 @@
+use crate::config::Config;
+use crate::window_manager::{Window, SCRATCHPAD};
+use crate::{
+    compute_workspace_names, name_workspaces, new_workspace_icons, pad_number, workspace_name,
+    workspace_number, workspace_number_in, Naming, NamingState,
+};
+use std::collections::BTreeMap;
+
+/// The name we give to the workspace with this name, as `name_workspaces` does
+fn new_workspace_name(c: &Config, name: &str, windows: &[Window], focused: bool) -> String {
+    let num = workspace_number_in(c, name);
+    let c = c.for_workspace(num);
+    let icons = new_workspace_icons(&c, name, num, windows, focused, &mut Naming::default());
+    workspace_name(&c, num, &icons)
+}
+
+#[test]
+fn test_number_is_leading_digits() {
+    assert_eq!("1", workspace_number("1", ": "));
+    assert_eq!("12", workspace_number("12: 🦊 ", ": "));
+}
+
+#[test]
+fn test_separator_in_icons() {
+    assert_eq!("3", workspace_number("3::: ", ":"));
+}
+
+#[test]
+fn test_separator_changed_since_last_rename() {
+    assert_eq!("4", workspace_number("4: 🦊 ", " | "));
+}
+
+#[test]
+fn test_name_without_number() {
+    assert_eq!("web", workspace_number("web: 🦊 ", ": "));
+}
+
+#[test]
+fn test_padding_is_idempotent() {
+    assert_eq!("01", pad_number("1", Some(2)));
+    assert_eq!("01", pad_number(workspace_number("01: 🦊 ", ": "), Some(2)));
+    assert_eq!("10", pad_number("10", Some(2)));
+}
+
+#[test]
+fn test_padding_leaves_names_untouched() {
+    assert_eq!("web", pad_number("web", Some(2)));
+    assert_eq!("1", pad_number("1", None));
+}
+
+#[test]
+fn test_empty_separator() {
+    let c = Config::from_str("\"firefox\" = \"🦊\"\n[other]\nseparator = \"\"").unwrap();
+    assert_eq!("", c.separator());
+    assert_eq!("2", workspace_number("2🦊 ", ""));
+    // Names without a number still need a separator
+    assert_eq!("web", workspace_number("web: 🦊 ", ""));
+}
+
+#[test]
+fn test_i3_named_workspaces_keep_their_name() {
+    assert_eq!("1:web", workspace_number("1:web", ": "));
+    assert_eq!("1:web", workspace_number("1:web: 🦊 ", ": "));
+    assert_eq!("10:mail", workspace_number("10:mail | ✉ ", " | "));
+    assert_eq!("2:code", workspace_number("2:code: 🦊 ", ""));
+}
+
+#[test]
+fn test_i3_workspaces_with_gaps_in_numbers() {
+    assert_eq!("3", workspace_number("3", ": "));
+    assert_eq!("7", workspace_number("7: 🦊 ", ": "));
+    assert_eq!("42", workspace_number("42:: 🦊 ", ":"));
+}
+
+#[test]
+fn test_alphabetic_workspace_names() {
+    assert_eq!("mail", workspace_number("mail", ": "));
+    assert_eq!("mail", workspace_number("mail: ✉ ", ": "));
+}
+
+#[test]
+fn test_focused_empty_icon() {
+    let c = Config::from_str("\"kitty\" = \"K\"\n[other]\nfocused_empty_icon = \"o\"").unwrap();
+    let kitty = [Window {
+        app_id: Some("kitty".to_string()),
+        ..Default::default()
+    }];
+    assert_eq!("1: o ", new_workspace_name(&c, "1", &[], true));
+    assert_eq!("1", new_workspace_name(&c, "1: o ", &[], false));
+    assert_eq!("2: K ", new_workspace_name(&c, "2", &kitty, true));
+}
+
+#[test]
+fn test_max_name_len() {
+    let c = Config::from_str("\"kitty\" = \"K\"\n[other]\nmax_name_len = 7").unwrap();
+    let kitty = |count| {
+        (0..count)
+            .map(|_| Window {
+                app_id: Some("kitty".to_string()),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!("1: K K ", new_workspace_name(&c, "1", &kitty(2), false));
+    assert_eq!("1: K K…", new_workspace_name(&c, "1", &kitty(3), false));
+    // The number is never cut
+    assert_eq!(
+        "123456: …",
+        new_workspace_name(&c, "123456", &kitty(3), false)
+    );
+}
+
+#[test]
+fn test_empty_icon_template() {
+    let c = Config::from_str(
+        "
+[other]
+empty_icon = \"({num})\"
+focused_empty_icon = \"o\"
+
+[workspace.9]
+empty_icon = \"mail\"
+",
+    )
+    .unwrap();
+    assert_eq!("1: (1) ", new_workspace_name(&c, "1", &[], false));
+    assert_eq!("9: mail ", new_workspace_name(&c, "9: K ", &[], false));
+    assert_eq!("2: o ", new_workspace_name(&c, "2", &[], true));
+}
+
+#[test]
+fn test_keep_label() {
+    let c = Config::from_str("\"kitty\" = \"K\"\n[other]\nkeep_label = true").unwrap();
+    let kitty = [Window {
+        app_id: Some("kitty".to_string()),
+        ..Default::default()
+    }];
+    assert_eq!(
+        "3: code K ",
+        new_workspace_name(&c, "3: code", &kitty, false)
+    );
+    // Renaming the workspace again doesn't repeat the label or the icons
+    assert_eq!(
+        "3: code K ",
+        new_workspace_name(&c, "3: code K ", &kitty, false)
+    );
+    assert_eq!("3: code ", new_workspace_name(&c, "3: code K ", &[], false));
+    // Nor does it when we don't remember the label, e.g. after restarting
+    assert_eq!(
+        "4: web K ",
+        new_workspace_name(&c, "4: web K K ", &kitty, false)
+    );
+    assert_eq!("5", new_workspace_name(&c, "5: K ", &[], false));
+}
+
+#[test]
+fn test_compute_workspace_names() {
+    let c = Config::from_str("\"kitty\" = \"K\"\n[other]\nfocused_empty_icon = \"o\"").unwrap();
+    let workspaces = BTreeMap::from([
+        (
+            "1".to_string(),
+            vec![Window {
+                app_id: Some("kitty".to_string()),
+                ..Default::default()
+            }],
+        ),
+        ("2: K ".to_string(), vec![]),
+        (SCRATCHPAD.to_string(), vec![]),
+    ]);
+    let names = name_workspaces(&c, &NamingState::default(), &workspaces, Some("2: K "));
+    assert_eq!(
+        BTreeMap::from([
+            ("1".to_string(), "1: K ".to_string()),
+            ("2: K ".to_string(), "2: o ".to_string()),
+        ]),
+        names.names
+    );
+    // As for the first names we give, with no focused workspace
+    assert_eq!(
+        BTreeMap::from([
+            ("1".to_string(), "1: K ".to_string()),
+            ("2: K ".to_string(), "2".to_string()),
+        ]),
+        compute_workspace_names(&c, &workspaces)
+    );
+    // Only the JSON output shows the scratchpad
+    assert_eq!(
+        BTreeMap::from([
+            ("1".to_string(), "K".to_string()),
+            ("2".to_string(), "o".to_string()),
+            (SCRATCHPAD.to_string(), "".to_string()),
+        ]),
+        names.icons
+    );
+}
+
+#[test]
+fn test_json_icons_follow_the_numbers_of_the_names() {
+    let c = Config::from_str(
+        "
+\"kitty\" = \"K\"
+
+[other]
+empty_icon = \"({num})\"
+keep_label = true
+max_name_len = 10
+number_width = 2
+",
+    )
+    .unwrap();
+    let kitty = |count| {
+        (0..count)
+            .map(|_| Window {
+                app_id: Some("kitty".to_string()),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>()
+    };
+    let workspaces = BTreeMap::from([
+        ("1".to_string(), vec![]),
+        ("2: code".to_string(), kitty(1)),
+        ("3".to_string(), kitty(4)),
+    ]);
+    let names = name_workspaces(&c, &NamingState::default(), &workspaces, None);
+    assert_eq!(
+        BTreeMap::from([
+            ("1".to_string(), "01: (1) ".to_string()),
+            ("2: code".to_string(), "02: code K ".to_string()),
+            ("3".to_string(), "03: K K K…".to_string()),
+        ]),
+        names.names
+    );
+    assert_eq!(
+        BTreeMap::from([
+            ("1".to_string(), "(1)".to_string()),
+            ("2".to_string(), "code K".to_string()),
+            ("3".to_string(), "K K K…".to_string()),
+        ]),
+        names.icons
+    );
+    // The status socket has the same names, by padded number
+    assert_eq!(
+        BTreeMap::from([
+            ("01".to_string(), "01: (1) ".to_string()),
+            ("02".to_string(), "02: code K ".to_string()),
+            ("03".to_string(), "03: K K K…".to_string()),
+        ]),
+        names.by_number
+    );
+}
+
+#[test]
+fn test_number_of_never_renamed_workspaces() {
+    assert_eq!("3", workspace_number("3", ": "));
+    assert_eq!("3", workspace_number("3:", ": "));
+    assert_eq!("3", workspace_number("3: ", ": "));
+    // A separator found in the number doesn't cut it short
+    assert_eq!("31", workspace_number("31", "1"));
+    assert_eq!("31", workspace_number("31", ""));
+}
+
+#[test]
+fn test_number_of_workspaces_renamed_by_us() {
+    assert_eq!("3", workspace_number("3: 🦊 ", ": "));
+    assert_eq!("3", workspace_number("3 | 🦊 ", ": "));
+}
+
+#[test]
+fn test_number_of_user_named_workspaces() {
+    assert_eq!("3", workspace_number("3: code", ": "));
+    assert_eq!("3:code", workspace_number("3:code", ": "));
+    assert_eq!("web", workspace_number("web", ": "));
+}
+
+#[test]
+fn test_renaming_keeps_the_number_from_any_initial_state() {
+    let c = Config::from_str("\"kitty\" = \"K\"").unwrap();
+    let kitty = [Window {
+        app_id: Some("kitty".to_string()),
+        ..Default::default()
+    }];
+    for name in ["3", "3:", "3: ", "3: K ", "3: K K ", "3: code"] {
+        let new_name = new_workspace_name(&c, name, &kitty, false);
+        assert_eq!("3: K ", new_name, "renaming: {name:?}");
+        // Renaming again is a no-op
+        assert_eq!(new_name, new_workspace_name(&c, &new_name, &kitty, false));
+    }
+    assert_eq!("3", new_workspace_name(&c, "3:", &[], false));
+}
+
+#[test]
+fn test_number_read_with_the_workspace_separator() {
+    let c = Config::from_str("\"kitty\" = \"K\"\n[workspace.web]\nseparator = \" | \"").unwrap();
+    let kitty = [Window {
+        app_id: Some("kitty".to_string()),
+        ..Default::default()
+    }];
+    assert_eq!("web | K ", new_workspace_name(&c, "web", &kitty, false));
+    assert_eq!(
+        "web | K ",
+        new_workspace_name(&c, "web | K ", &kitty, false)
+    );
+    assert_eq!("web", new_workspace_name(&c, "web | K ", &[], false));
+}
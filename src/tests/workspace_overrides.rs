@@ -0,0 +1,83 @@
+use crate::config::Config;
+use crate::window_manager::Window;
+use crate::{pretty_window, workspace_icons, Naming};
+
+const CONFIG_OVERRIDES: &str = "
+[other]
+fallback_icon = \"?\"
+separator = \": \"
+
+[workspace.9]
+fallback_icon = \"!\"
+separator = \" | \"
+prefix = \"mail\"
+";
+
+#[test]
+fn test_overrides_beat_other() {
+    let c = Config::from_str(CONFIG_OVERRIDES).unwrap();
+    let w = Window::default();
+    assert_eq!("?", pretty_window(&c.for_workspace("1"), &w));
+    assert_eq!("!", pretty_window(&c.for_workspace("9"), &w));
+    assert_eq!(" | ", c.for_workspace("9").separator());
+}
+
+#[test]
+fn test_overrides_with_padded_number() {
+    let c = Config::from_str(CONFIG_OVERRIDES).unwrap();
+    let w = Window::default();
+    assert_eq!("!", pretty_window(&c.for_workspace("09"), &w));
+}
+
+#[test]
+fn test_prefix_comes_before_icons() {
+    let c = Config::from_str(CONFIG_OVERRIDES).unwrap();
+    let c = c.for_workspace("9");
+    assert_eq!(
+        "mail ! ",
+        workspace_icons(&c, "9", &[Window::default()], &mut Naming::default())
+    );
+    assert_eq!(
+        "mail ",
+        workspace_icons(&c, "9", &[], &mut Naming::default())
+    );
+}
+
+#[test]
+fn test_backend_overrides() {
+    let config = || {
+        Config::from_str(
+            "
+[other]
+fallback_icon = \"?\"
+separator = \" | \"
+
+[i3]
+fallback_icon = \"!\"
+",
+        )
+        .unwrap()
+    };
+    let w = Window {
+        app_id: Some("unknown".to_string()),
+        ..Default::default()
+    };
+    let mut c = config();
+    c.apply_backend("Sway").unwrap();
+    assert_eq!("?", pretty_window(&c, &w));
+    let mut c = config();
+    c.apply_backend("i3").unwrap();
+    assert_eq!("!", pretty_window(&c, &w));
+    // The settings that aren't overridden are kept
+    assert_eq!(" | ", c.separator());
+}
+
+#[test]
+fn test_separators_validated_once_per_load() {
+    let mut c = Config::from_str(CONFIG_OVERRIDES).unwrap();
+    c.validate_separators();
+    // The views of the config get the separators validated for their workspace
+    assert_eq!(" | ", c.for_workspace("09").separator());
+    assert_eq!(": ", c.for_workspace("1").separator());
+    assert_eq!(": ", c.separator());
+}
@@ -1,29 +1,53 @@
-use crate::EnforceWindowManager;
+use crate::config::{Config, HyprlandEvent, WindowField};
+use crate::{runtime_file, shutdown_requested, EnforceWindowManager};
 use anyhow::{anyhow, bail, Context, Result};
-use hyprland::data::{Clients, Version, Workspaces};
+use hyprland::data::{Client, Clients, Version, Workspace, Workspaces};
 use hyprland::dispatch::{Dispatch, DispatchType};
 use hyprland::event_listener::EventListener;
-use hyprland::shared::HyprData;
+use hyprland::shared::{HyprData, HyprDataActive, HyprDataActiveOptional, WorkspaceId};
 use itertools::Itertools;
-use std::collections::BTreeMap;
-use std::sync::{mpsc, mpsc::Receiver};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use swayipc::{Connection, EventStream, EventType, Node, NodeType};
+use std::time::{Duration, Instant};
+use swayipc::{
+    Connection, Event, EventType, Fallible, Node, NodeType, WindowChange, WorkspaceChange,
+    WorkspaceEvent,
+};
+use zbus::dbus_interface;
+
+/// Name of the special workspace i3 and sway use for the scratchpad
+pub const SCRATCHPAD: &str = "__i3_scratch";
 
 trait NodeExt {
+    fn is_scratchpad(&self) -> bool;
     fn is_workspace(&self) -> bool;
     fn is_window(&self) -> bool;
     fn name(&self) -> Option<String>;
     fn app_id(&self) -> Option<String>;
     fn window_properties_class(&self) -> Option<String>;
-    fn windows_in_node(&self) -> Vec<Window>;
-    fn workspaces_in_node(&self) -> Result<BTreeMap<String, Vec<Window>>>;
+    fn window_properties_instance(&self) -> Option<String>;
+    fn windows_in_node(&self, config: &Config) -> Vec<Window>;
+    fn workspaces_in_node(&self, config: &Config) -> Result<BTreeMap<String, Vec<Window>>>;
+    fn to_json(&self) -> serde_json::Value;
 }
 
 impl NodeExt for Node {
+    fn is_scratchpad(&self) -> bool {
+        self.name.as_deref() == Some(SCRATCHPAD) && self.node_type == NodeType::Workspace
+    }
     fn is_workspace(&self) -> bool {
-        // `__i3_scratch` is a special workspace that connot be renamed, so we just skip it
-        self.name.as_deref() != Some("__i3_scratch") && self.node_type == NodeType::Workspace
+        // The scratchpad is a special workspace that connot be renamed, so it isn't considered as
+        // a regular workspace
+        !self.is_scratchpad() && self.node_type == NodeType::Workspace
     }
     fn is_window(&self) -> bool {
         matches!(self.node_type, NodeType::Con | NodeType::FloatingCon)
@@ -39,13 +63,23 @@ impl NodeExt for Node {
             .as_ref()
             .and_then(|prop| prop.class.clone())
     }
+    fn window_properties_instance(&self) -> Option<String> {
+        self.window_properties
+            .as_ref()
+            .and_then(|prop| prop.instance.clone())
+    }
     /// Recursively find all windows names in this node
-    fn windows_in_node(&self) -> Vec<Window> {
+    fn windows_in_node(&self, config: &Config) -> Vec<Window> {
         let mut res = Vec::new();
-        for node in self.nodes.iter().chain(self.floating_nodes.iter()) {
-            res.extend(node.windows_in_node());
+        let floating_nodes = if config.include_floating() {
+            self.floating_nodes.as_slice()
+        } else {
+            &[]
+        };
+        for node in self.nodes.iter().chain(floating_nodes) {
+            res.extend(node.windows_in_node(config));
             if node.is_window() {
-                if let Some(window) = Window::from_node(node) {
+                if let Some(window) = Window::from_node(node, config) {
                     res.push(window);
                 }
             }
@@ -53,17 +87,24 @@ impl NodeExt for Node {
         res
     }
     /// Recursively find all workspaces in this node and the list of open windows for each of these
-    /// workspaces
-    fn workspaces_in_node(&self) -> Result<BTreeMap<String, Vec<Window>>> {
+    /// workspaces.
+    /// The scratchpad is only included if the config asks to show it.
+    fn workspaces_in_node(&self, config: &Config) -> Result<BTreeMap<String, Vec<Window>>> {
         let mut res = BTreeMap::new();
         for node in &self.nodes {
-            if node.is_workspace() {
+            let name = node.name.as_deref().unwrap_or_default();
+            if node.is_workspace() && config.ignores_workspace(name)
+                || node.node_type == NodeType::Output && config.ignores_output(name)
+            {
+                continue;
+            }
+            if node.is_workspace() || (node.is_scratchpad() && config.other.show_scratchpad) {
                 res.insert(
                     node.name().context("Expected some node name")?,
-                    node.windows_in_node(),
+                    node.windows_in_node(config),
                 );
-            } else {
-                let workspaces = node.workspaces_in_node()?;
+            } else if !node.is_scratchpad() {
+                let workspaces = node.workspaces_in_node(config)?;
                 for (k, v) in workspaces {
                     res.insert(k, v);
                 }
@@ -71,140 +112,424 @@ impl NodeExt for Node {
         }
         Ok(res)
     }
+    /// The fields of this node and its children that matter to identify windows
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "node_type": format!("{:?}", self.node_type),
+            "name": self.name,
+            "app_id": self.app_id,
+            "class": self.window_properties_class(),
+            "instance": self.window_properties_instance(),
+            "fullscreen_mode": self.fullscreen_mode,
+            "marks": self.marks,
+            "nodes": self.nodes.iter().map(Node::to_json).collect::<Vec<_>>(),
+            "floating_nodes": self.floating_nodes.iter().map(Node::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Identifies a window for as long as it is open
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WindowId {
+    /// Id of the sway/i3 node
+    Node(i64),
+    /// Address of the Hyprland client
+    Address(String),
+    /// Internal id of the KWin window
+    Uuid(String),
+    /// Id of the Mutter window
+    Mutter(u64),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Window {
-    pub(crate) name: Option<String>,
-    pub(crate) app_id: Option<String>,
-    pub(crate) window_properties_class: Option<String>,
+    pub id: Option<WindowId>,
+    pub name: Option<String>,
+    pub app_id: Option<String>,
+    pub window_properties_class: Option<String>,
+    /// Instance part of the X11 WM_CLASS, which some apps use to identify themselves instead of
+    /// the class
+    pub window_properties_instance: Option<String>,
+    /// Whether the window is asking for attention
+    pub urgent: bool,
+    /// Whether the window has the keyboard focus
+    pub focused: bool,
+    pub fullscreen: bool,
+    pub floating: bool,
+    /// The sway/i3 marks of the window, only matched by rules restricted to them
+    pub marks: Vec<String>,
 }
 
 impl Window {
-    fn from_node(node: &Node) -> Option<Self> {
+    fn from_node(node: &Node, config: &Config) -> Option<Self> {
         if node.is_window() {
-            let name = node.name();
-            let app_id = node.app_id();
-            let window_properties_class = node.window_properties_class();
-            if name.is_some() || app_id.is_some() || window_properties_class.is_some() {
-                Some(Self {
-                    name,
-                    app_id,
-                    window_properties_class,
-                })
-            } else {
-                None
-            }
+            let window = Self {
+                id: Some(WindowId::Node(node.id)),
+                name: node.name(),
+                app_id: node.app_id(),
+                window_properties_class: node.window_properties_class(),
+                window_properties_instance: node.window_properties_instance(),
+                urgent: node.urgent,
+                focused: node.focused,
+                fullscreen: node.fullscreen_mode.map_or(false, |mode| mode != 0),
+                floating: node.node_type == NodeType::FloatingCon,
+                marks: node.marks.clone(),
+            };
+            // The containers splitting the screen between windows have none of the fields either
+            let container = !node.nodes.is_empty() || !node.floating_nodes.is_empty();
+            (window.exists() || (!container && window.is_shown(config))).then_some(window)
         } else {
             None
         }
     }
     fn exists(&self) -> bool {
-        self.name.is_some() || self.app_id.is_some() || self.window_properties_class.is_some()
+        self.fields().next().is_some()
     }
-    pub fn matches(&self, pattern: &str) -> bool {
-        self.name
-            .as_ref()
-            .map(|s| s.to_lowercase().contains(pattern))
-            .unwrap_or(false)
-            || self
-                .app_id
-                .as_ref()
-                .map(|s| s.to_lowercase().contains(pattern))
-                .unwrap_or(false)
-            || self
-                .window_properties_class
-                .as_ref()
-                .map(|s| s.to_lowercase().contains(pattern))
-                .unwrap_or(false)
+    /// Whether the window gets an icon. The windows we know nothing about are left out, unless
+    /// they get the `blank_icon`
+    pub(crate) fn is_shown(&self, config: &Config) -> bool {
+        self.exists() || config.other.blank_icon.is_some()
+    }
+    /// All the fields identifying this window
+    pub fn fields(&self) -> impl Iterator<Item = &str> + '_ {
+        [
+            &self.name,
+            &self.app_id,
+            &self.window_properties_class,
+            &self.window_properties_instance,
+        ]
+        .into_iter()
+        .filter_map(|field| field.as_deref())
+    }
+    /// All the present fields joined by spaces, identifiers first then the title, so
+    /// `firefox youtube` matches a YouTube tab in firefox
+    fn combined_fields(&self) -> String {
+        [
+            &self.app_id,
+            &self.window_properties_class,
+            &self.window_properties_instance,
+            &self.name,
+        ]
+        .into_iter()
+        .filter_map(|field| field.as_deref())
+        .join(" ")
+    }
+    /// The value of one of the fields of the window, the first mark for the marks
+    pub fn field(&self, field: WindowField) -> Option<&str> {
+        match field {
+            WindowField::Mark => self.marks.first().map(String::as_str),
+            WindowField::Name => self.name.as_deref(),
+            WindowField::AppId => self.app_id.as_deref(),
+            WindowField::Class => self.window_properties_class.as_deref(),
+            WindowField::Instance => self.window_properties_instance.as_deref(),
+        }
+    }
+    /// Whether the pattern matches any of the window's fields, or only the one from the options.
+    /// See `pattern_matches`
+    pub fn matches(&self, pattern: &str, options: MatchOptions) -> bool {
+        match options.field {
+            Some(WindowField::Mark) => {
+                return self
+                    .marks
+                    .iter()
+                    .any(|mark| pattern_matches(pattern, mark, options.normalize_separators));
+            }
+            Some(field) => {
+                return self.field(field).map_or(false, |field| {
+                    pattern_matches(pattern, field, options.normalize_separators)
+                });
+            }
+            None => {}
+        }
+        self.fields()
+            .any(|field| pattern_matches(pattern, field, options.normalize_separators))
+            || (options.combined_fields
+                && pattern_matches(
+                    pattern,
+                    &self.combined_fields(),
+                    options.normalize_separators,
+                ))
+    }
+}
+
+/// How patterns are matched against windows
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MatchOptions {
+    /// Consider `-` and `_` as the same character
+    pub normalize_separators: bool,
+    /// Also match against all the fields of the window joined by spaces
+    pub combined_fields: bool,
+    /// Only match against this field
+    pub field: Option<WindowField>,
+}
+
+/// Substring match against the lowercased text, so lowercase patterns match it whatever its case.
+/// A leading `^` anchors the pattern to the start of the text and a trailing `$` to its end,
+/// so `^code$` only matches text that is exactly `code`.
+/// With `normalize_separators`, `-` and `_` are considered as the same character.
+pub fn pattern_matches(pattern: &str, text: &str, normalize_separators: bool) -> bool {
+    let normalize = |s: String| {
+        if normalize_separators {
+            s.replace('_', "-")
+        } else {
+            s
+        }
+    };
+    let pattern = normalize(pattern.to_string());
+    let (pattern, anchored_start) = match pattern.strip_prefix('^') {
+        Some(pattern) => (pattern, true),
+        None => (pattern.as_str(), false),
+    };
+    let (pattern, anchored_end) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let text = normalize(text.to_lowercase());
+    match (anchored_start, anchored_end) {
+        (true, true) => text == pattern,
+        (true, false) => text.starts_with(pattern),
+        (false, true) => text.ends_with(pattern),
+        (false, false) => text.contains(pattern),
     }
 }
 
 pub trait WM {
-    fn connect(enforce: Option<EnforceWindowManager>) -> Result<Box<Self>>;
-    fn get_windows_in_each_workspace(&mut self) -> Result<BTreeMap<String, Vec<Window>>>;
+    /// Connect to the window manager. `socket` is the path of the sway/i3 ipc socket, to use
+    /// instead of the one from the environment
+    fn connect(enforce: Option<EnforceWindowManager>, socket: Option<&Path>) -> Result<Box<Self>>;
+    /// The name of the window manager we're connected to
+    fn name(&self) -> &'static str;
+    fn get_windows_in_each_workspace(
+        &mut self,
+        config: &Config,
+    ) -> Result<BTreeMap<String, Vec<Window>>>;
     fn rename_workspace(&mut self, old: &str, new: &str) -> Result<()>;
-    fn wait_for_event(&mut self) -> Result<()>;
+    /// The name of the focused workspace, if any
+    fn focused_workspace(&mut self) -> Result<Option<String>>;
+    /// Rename several workspaces at once, from their old name to their new name.
+    /// A workspace may have been closed or renamed since we got its name, so failing to rename
+    /// it is only logged. Errors are for problems with the connection itself
+    fn rename_workspaces(&mut self, renames: &[(String, String)]) -> Result<()> {
+        for (old, new) in renames {
+            if let Err(e) = self.rename_workspace(old, new) {
+                warn!("{e:#}");
+            }
+        }
+        Ok(())
+    }
+    /// Wait for the next relevant event, or for the refresh interval from the config to expire if
+    /// there is one. Returns what changed
+    fn wait_for_event(&mut self, config: &Config) -> Result<Change>;
+    /// The windows as seen by the window manager, to debug how they are identified
+    fn dump_tree(&mut self) -> Result<serde_json::Value>;
+}
+
+/// What changed according to an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// Only the workspace containing this window changed
+    Window(WindowId),
+    /// Any workspace may have changed
+    Unknown,
+    /// We've been asked to exit, so we should stop waiting for events
+    Shutdown,
+}
+
+/// How often we check whether we've been asked to exit while waiting for events
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Receive from the channel, waiting at most for the timeout if there is one.
+/// Returns None if the timeout expired or if we've been asked to exit
+fn recv<T>(rx: &Receiver<T>, timeout: Option<Duration>) -> Result<Option<T>> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        if shutdown_requested() {
+            return Ok(None);
+        }
+        let wait = deadline.map_or(SHUTDOWN_POLL_INTERVAL, |deadline| {
+            deadline
+                .saturating_duration_since(Instant::now())
+                .min(SHUTDOWN_POLL_INTERVAL)
+        });
+        match rx.recv_timeout(wait) {
+            Ok(event) => return Ok(Some(event)),
+            Err(RecvTimeoutError::Timeout) => {
+                if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                    return Ok(None);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => bail!("Event stream ended"),
+        }
+    }
 }
 
 pub enum WindowManager {
     SwayOrI3(Box<SwayOrI3>),
     Hyprland(Box<Hyprland>),
+    Kwin(Box<Kwin>),
+    Gnome(Box<Gnome>),
 }
 
 impl WM for WindowManager {
-    fn connect(enforce: Option<EnforceWindowManager>) -> Result<Box<Self>> {
+    fn connect(enforce: Option<EnforceWindowManager>, socket: Option<&Path>) -> Result<Box<Self>> {
         let connect_to_sway_or_i3 =
-            || SwayOrI3::connect(enforce).map(|wm| Box::new(Self::SwayOrI3(wm)));
+            || SwayOrI3::connect(enforce, socket).map(|wm| Box::new(Self::SwayOrI3(wm)));
         let connect_to_hyprland =
-            || Hyprland::connect(enforce).map(|wm| Box::new(Self::Hyprland(wm)));
+            || Hyprland::connect(enforce, socket).map(|wm| Box::new(Self::Hyprland(wm)));
+        let connect_to_kwin = || Kwin::connect(enforce, socket).map(|wm| Box::new(Self::Kwin(wm)));
+        let connect_to_gnome =
+            || Gnome::connect(enforce, socket).map(|wm| Box::new(Self::Gnome(wm)));
         match enforce {
             Some(EnforceWindowManager::SwayOrI3) => connect_to_sway_or_i3(),
             Some(EnforceWindowManager::Hyprland) => connect_to_hyprland(),
+            Some(EnforceWindowManager::Kwin) => connect_to_kwin(),
+            Some(EnforceWindowManager::Gnome) => connect_to_gnome(),
             None => {
-                connect_to_sway_or_i3().or_else(|_| connect_to_hyprland()).map_err(|_| anyhow!("Couldn't connect to the window manager. Only Sway, I3 and Hyprland are officially supported."))
+                connect_to_sway_or_i3().or_else(|_| connect_to_hyprland()).or_else(|_| connect_to_kwin()).or_else(|_| connect_to_gnome()).map_err(|_| anyhow!("Couldn't connect to the window manager. Only Sway, I3, Hyprland, KWin and GNOME Shell are officially supported."))
             }
 
         }
     }
-    fn get_windows_in_each_workspace(&mut self) -> Result<BTreeMap<String, Vec<Window>>> {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::SwayOrI3(wm) => wm.name(),
+            Self::Hyprland(wm) => wm.name(),
+            Self::Kwin(wm) => wm.name(),
+            Self::Gnome(wm) => wm.name(),
+        }
+    }
+    fn get_windows_in_each_workspace(
+        &mut self,
+        config: &Config,
+    ) -> Result<BTreeMap<String, Vec<Window>>> {
         match self {
-            Self::SwayOrI3(wm) => wm.get_windows_in_each_workspace(),
-            Self::Hyprland(wm) => wm.get_windows_in_each_workspace(),
+            Self::SwayOrI3(wm) => wm.get_windows_in_each_workspace(config),
+            Self::Hyprland(wm) => wm.get_windows_in_each_workspace(config),
+            Self::Kwin(wm) => wm.get_windows_in_each_workspace(config),
+            Self::Gnome(wm) => wm.get_windows_in_each_workspace(config),
         }
     }
     fn rename_workspace(&mut self, old: &str, new: &str) -> Result<()> {
         match self {
             Self::SwayOrI3(wm) => wm.rename_workspace(old, new),
             Self::Hyprland(wm) => wm.rename_workspace(old, new),
+            Self::Kwin(wm) => wm.rename_workspace(old, new),
+            Self::Gnome(wm) => wm.rename_workspace(old, new),
+        }
+    }
+    fn focused_workspace(&mut self) -> Result<Option<String>> {
+        match self {
+            Self::SwayOrI3(wm) => wm.focused_workspace(),
+            Self::Hyprland(wm) => wm.focused_workspace(),
+            Self::Kwin(wm) => wm.focused_workspace(),
+            Self::Gnome(wm) => wm.focused_workspace(),
         }
     }
-    fn wait_for_event(&mut self) -> Result<()> {
+    fn rename_workspaces(&mut self, renames: &[(String, String)]) -> Result<()> {
         match self {
-            Self::SwayOrI3(wm) => wm.wait_for_event(),
-            Self::Hyprland(wm) => wm.wait_for_event(),
+            Self::SwayOrI3(wm) => wm.rename_workspaces(renames),
+            Self::Hyprland(wm) => wm.rename_workspaces(renames),
+            Self::Kwin(wm) => wm.rename_workspaces(renames),
+            Self::Gnome(wm) => wm.rename_workspaces(renames),
+        }
+    }
+    fn wait_for_event(&mut self, config: &Config) -> Result<Change> {
+        let change = match self {
+            Self::SwayOrI3(wm) => wm.wait_for_event(config),
+            Self::Hyprland(wm) => wm.wait_for_event(config),
+            Self::Kwin(wm) => wm.wait_for_event(config),
+            Self::Gnome(wm) => wm.wait_for_event(config),
+        }?;
+        // The window managers stop waiting as soon as we're asked to exit
+        if shutdown_requested() {
+            Ok(Change::Shutdown)
+        } else {
+            Ok(change)
+        }
+    }
+    fn dump_tree(&mut self) -> Result<serde_json::Value> {
+        match self {
+            Self::SwayOrI3(wm) => wm.dump_tree(),
+            Self::Hyprland(wm) => wm.dump_tree(),
+            Self::Kwin(wm) => wm.dump_tree(),
+            Self::Gnome(wm) => wm.dump_tree(),
         }
     }
 }
 
+/// How many times we try to reach Hyprland while it is starting, and how long we wait in between
+const HYPRLAND_CONNECT_ATTEMPTS: usize = 10;
+const HYPRLAND_CONNECT_INTERVAL: Duration = Duration::from_millis(500);
+
 pub struct Hyprland {
-    rx: Receiver<()>,
+    rx: Receiver<(HyprlandEvent, Change)>,
+    /// Id of each workspace by name, as of the last time we got them, since Hyprland renames
+    /// workspaces by id
+    ids: HashMap<String, WorkspaceId>,
+}
+
+/// The name of a Hyprland workspace, which is its id until it gets renamed
+fn hyprland_workspace_name(name: &str, id: WorkspaceId) -> String {
+    if name.is_empty() {
+        id.to_string()
+    } else {
+        name.to_string()
+    }
 }
 
 impl WM for Hyprland {
-    fn connect(enforce: Option<EnforceWindowManager>) -> Result<Box<Self>> {
+    fn connect(enforce: Option<EnforceWindowManager>, _socket: Option<&Path>) -> Result<Box<Self>> {
         match enforce {
             None | Some(EnforceWindowManager::Hyprland) => {
-                Version::get()?;
+                // Hyprland sets this for the programs it starts, before its socket is ready
+                if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_none() {
+                    bail!("Hyprland isn't running: HYPRLAND_INSTANCE_SIGNATURE isn't set");
+                }
+                let mut attempts = 0;
+                while let Err(e) = Version::get() {
+                    attempts += 1;
+                    if attempts >= HYPRLAND_CONNECT_ATTEMPTS {
+                        return Err(anyhow!(e)
+                            .context("Hyprland is running but its IPC socket isn't ready"));
+                    }
+                    info!("Waiting for the Hyprland IPC socket: {e}");
+                    thread::sleep(HYPRLAND_CONNECT_INTERVAL);
+                }
                 let (tx, rx) = mpsc::channel();
+                // Sending fails once we've reconnected and dropped the receiver, which is fine
                 thread::spawn(move || {
                     let mut listener = EventListener::new();
                     let tx_clone = tx.clone();
-                    listener.add_window_open_handler(move |_| {
-                        tx_clone.send(()).unwrap();
+                    listener.add_window_open_handler(move |event| {
+                        let id = WindowId::Address(event.window_address.to_string());
+                        let _ = tx_clone.send((HyprlandEvent::Window, Change::Window(id)));
                     });
                     let tx_clone = tx.clone();
                     listener.add_window_close_handler(move |_| {
-                        tx_clone.send(()).unwrap();
+                        let _ = tx_clone.send((HyprlandEvent::Window, Change::Unknown));
                     });
                     let tx_clone = tx.clone();
                     listener.add_window_moved_handler(move |_| {
-                        tx_clone.send(()).unwrap();
+                        let _ = tx_clone.send((HyprlandEvent::Window, Change::Unknown));
                     });
                     let tx_clone = tx.clone();
                     listener.add_layer_open_handler(move |_| {
-                        tx_clone.send(()).unwrap();
+                        let _ = tx_clone.send((HyprlandEvent::Layer, Change::Unknown));
                     });
                     let tx_clone = tx.clone();
                     listener.add_layer_closed_handler(move |_| {
-                        tx_clone.send(()).unwrap();
+                        let _ = tx_clone.send((HyprlandEvent::Layer, Change::Unknown));
                     });
                     listener.add_workspace_change_handler(move |_| {
-                        tx.send(()).unwrap();
+                        let _ = tx.send((HyprlandEvent::Workspace, Change::Unknown));
                     });
                     listener.start_listener().map_err(|e| anyhow!(e)).unwrap();
                 });
-                Ok(Box::new(Self { rx }))
+                Ok(Box::new(Self {
+                    rx,
+                    ids: HashMap::new(),
+                }))
             }
             _ => {
                 bail!("Not connecting to Hyprland as we've been explicitly asked not to")
@@ -212,21 +537,53 @@ impl WM for Hyprland {
         }
     }
 
-    fn get_windows_in_each_workspace(&mut self) -> Result<BTreeMap<String, Vec<Window>>> {
-        let empty_workspaces = Workspaces::get()
+    fn name(&self) -> &'static str {
+        "Hyprland"
+    }
+
+    fn get_windows_in_each_workspace(
+        &mut self,
+        config: &Config,
+    ) -> Result<BTreeMap<String, Vec<Window>>> {
+        let active_client = Client::get_active()
+            .context("Failed to get the active client")?
+            .map(|client| client.address);
+        let workspaces: Vec<_> = Workspaces::get()
             .context("Failed to get workspaces")?
-            .filter_map(|workspace| {
-                if workspace.windows == 0 {
-                    Some((format!("{}", workspace.id), Vec::new()))
-                } else {
-                    None
-                }
-            });
+            // Special workspaces can't be renamed, like the sway/i3 scratchpad
+            .filter(|workspace| {
+                workspace.id >= 0
+                    && !config.ignores_workspace(&workspace.name)
+                    && !config.ignores_output(&workspace.monitor)
+            })
+            .collect();
+        self.ids = workspaces
+            .iter()
+            .map(|workspace| {
+                (
+                    hyprland_workspace_name(&workspace.name, workspace.id),
+                    workspace.id,
+                )
+            })
+            .collect();
+        let empty_workspaces = workspaces.into_iter().filter_map(|workspace| {
+            if workspace.windows == 0 {
+                Some((
+                    hyprland_workspace_name(&workspace.name, workspace.id),
+                    Vec::new(),
+                ))
+            } else {
+                None
+            }
+        });
         Ok(Clients::get()
             .context("Failed to get clients")?
+            .filter(|client| config.include_floating() || !client.floating)
+            // Only the windows of the workspaces we don't ignore
+            .filter(|client| self.ids.values().any(|&id| id == client.workspace.id))
             .map(|client| {
                 (
-                    client.workspace.id,
+                    hyprland_workspace_name(&client.workspace.name, client.workspace.id),
                     (
                         // Keep the position so the order of the icons matches the order of the
                         // windows on the screen, from left to right then top to bottom
@@ -235,6 +592,7 @@ impl WM for Hyprland {
                             client.at.0, /* x position in px */
                         ),
                         Window {
+                            id: Some(WindowId::Address(client.address.to_string())),
                             name: match client.title.as_str() {
                                 "" => None,
                                 s => Some(s.to_string()),
@@ -244,6 +602,13 @@ impl WM for Hyprland {
                                 "" => None,
                                 s => Some(s.to_string()),
                             },
+                            window_properties_instance: None,
+                            // Hyprland clients don't expose their urgency
+                            urgent: false,
+                            focused: Some(&client.address) == active_client.as_ref(),
+                            fullscreen: client.fullscreen,
+                            floating: client.floating,
+                            marks: Vec::new(),
                         },
                     ),
                 )
@@ -254,11 +619,11 @@ impl WM for Hyprland {
                 // Sort by position
                 v.sort_by(|(l, _), (r, _)| l.cmp(r));
                 (
-                    format!("{k}"),
+                    k,
                     v.into_iter()
                         // We don't need the position anymore. Dismiss it
                         .map(|(_pos, w)| w)
-                        .filter(|w| w.exists())
+                        .filter(|w| w.is_shown(config))
                         .collect(),
                 )
             })
@@ -267,42 +632,642 @@ impl WM for Hyprland {
     }
 
     fn rename_workspace(&mut self, old: &str, new: &str) -> Result<()> {
-        Dispatch::call(DispatchType::RenameWorkspace(
-            old.parse().context("Failed to parse workspace id")?,
-            Some(new),
-        ))
-        .context(format!("Failed to rename workspace from {old} to {new}"))
+        let id = match self.ids.get(old) {
+            Some(&id) => id,
+            None => old.parse().context("Failed to parse workspace id")?,
+        };
+        Dispatch::call(DispatchType::RenameWorkspace(id, Some(new)))
+            .context(format!("Failed to rename workspace from {old} to {new}"))
+    }
+
+    fn focused_workspace(&mut self) -> Result<Option<String>> {
+        let workspace = Workspace::get_active().context("Failed to get the active workspace")?;
+        Ok(Some(hyprland_workspace_name(&workspace.name, workspace.id)))
+    }
+
+    fn wait_for_event(&mut self, config: &Config) -> Result<Change> {
+        let deadline = config
+            .refresh_interval()
+            .map(|interval| Instant::now() + interval);
+        loop {
+            let timeout =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            match recv(&self.rx, timeout).context("Failed to wait for event")? {
+                Some((event, _)) if !config.hyprland_events().contains(&event) => continue,
+                Some((_, change)) => return Ok(change),
+                None => return Ok(Change::Unknown),
+            }
+        }
+    }
+
+    fn dump_tree(&mut self) -> Result<serde_json::Value> {
+        Ok(Clients::get()
+            .context("Failed to get clients")?
+            .map(|client| {
+                json!({
+                    "address": client.address.to_string(),
+                    "workspace": client.workspace.id,
+                    "class": client.class,
+                    "title": client.title,
+                    "floating": client.floating,
+                    "fullscreen": client.fullscreen,
+                    "at": [client.at.0, client.at.1],
+                })
+            })
+            .collect())
+    }
+}
+
+/// D-Bus name of KWin
+const KWIN_SERVICE: &str = "org.kde.KWin";
+
+/// Name under which our script is loaded into KWin
+const KWIN_PLUGIN: &str = "workstyle";
+
+/// The KWin script reporting the desktops and windows to us
+pub(crate) const KWIN_SCRIPT: &str = include_str!("kwin.js");
+
+/// How long we give our KWin script to send its first report
+const KWIN_SCRIPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A KWin virtual desktop, as reported by our script
+#[derive(Debug, Deserialize, Serialize)]
+struct KwinDesktop {
+    id: String,
+    name: String,
+    /// Position of the desktop, starting from 1
+    position: usize,
+}
+
+/// A KWin window, as reported by our script
+#[derive(Debug, Deserialize, Serialize)]
+struct KwinWindow {
+    id: String,
+    caption: String,
+    desktop_file_name: String,
+    resource_class: String,
+    resource_name: String,
+    /// Ids of the desktops the window is on
+    desktops: Vec<String>,
+    on_all_desktops: bool,
+    urgent: bool,
+    focused: bool,
+    fullscreen: bool,
+    x: f64,
+    y: f64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct KwinState {
+    desktops: Vec<KwinDesktop>,
+    /// Id of the current desktop
+    current: String,
+    windows: Vec<KwinWindow>,
+}
+
+/// Receives the reports of our KWin script over D-Bus
+pub(crate) struct KwinReports(pub(crate) Mutex<Sender<String>>);
+
+#[dbus_interface(name = "org.workstyle.Kwin")]
+impl KwinReports {
+    /// Exported as is, as the script calls it `report` rather than the default `Report`
+    #[dbus_interface(name = "report")]
+    fn report(&self, state: String) {
+        // Sending fails once we've reconnected and dropped the receiver, which is fine
+        let _ = self.0.lock().unwrap().send(state);
+    }
+}
+
+/// The name of a KWin desktop or GNOME workspace, which is its position (starting from 1) until
+/// it gets renamed with a number, as they're named like `Desktop <position>` by default
+fn numbered_name(name: &str, position: usize) -> String {
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.to_string()
+    } else {
+        position.to_string()
+    }
+}
+
+fn kwin_desktop_name(desktop: &KwinDesktop) -> String {
+    numbered_name(&desktop.name, desktop.position)
+}
+
+pub struct Kwin {
+    connection: zbus::blocking::Connection,
+    rx: Receiver<String>,
+    /// The last state reported by our script
+    state: KwinState,
+    /// Id of each desktop by name, as of the last time we got them, since KWin renames desktops
+    /// by id
+    ids: HashMap<String, String>,
+}
+
+impl Kwin {
+    /// Call a method of KWin
+    fn call<B>(
+        &self,
+        path: &str,
+        interface: &str,
+        method: &str,
+        body: &B,
+    ) -> Result<Arc<zbus::Message>>
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        self.connection
+            .call_method(Some(KWIN_SERVICE), path, Some(interface), method, body)
+            .with_context(|| format!("Failed to call KWin method: {method}"))
+    }
+
+    /// Load our script into KWin and start it
+    fn load_script(&self) -> Result<()> {
+        let service = self
+            .connection
+            .unique_name()
+            .context("Not connected to the session bus")?
+            .to_string();
+        let path = runtime_file("workstyle-kwin", "js");
+        fs::write(&path, KWIN_SCRIPT.replace("%SERVICE%", &service))
+            .with_context(|| format!("Failed to write KWin script: {path:?}"))?;
+        // Left behind by a previous connection
+        self.call(
+            "/Scripting",
+            "org.kde.kwin.Scripting",
+            "unloadScript",
+            &KWIN_PLUGIN,
+        )?;
+        let id: i32 = self
+            .call(
+                "/Scripting",
+                "org.kde.kwin.Scripting",
+                "loadScript",
+                &(path.to_string_lossy().as_ref(), KWIN_PLUGIN),
+            )?
+            .body()
+            .context("Failed to load KWin script")?;
+        self.call(
+            &format!("/Scripting/Script{id}"),
+            "org.kde.kwin.Script",
+            "run",
+            &(),
+        )?;
+        Ok(())
+    }
+
+    /// Keep the last state reported by our script
+    fn update(&mut self, state: &str) -> Result<()> {
+        self.state = serde_json::from_str(state).context("Invalid report from KWin script")?;
+        Ok(())
+    }
+}
+
+impl Drop for Kwin {
+    fn drop(&mut self) {
+        let _ = self.call(
+            "/Scripting",
+            "org.kde.kwin.Scripting",
+            "unloadScript",
+            &KWIN_PLUGIN,
+        );
+    }
+}
+
+impl WM for Kwin {
+    fn connect(enforce: Option<EnforceWindowManager>, _socket: Option<&Path>) -> Result<Box<Self>> {
+        match enforce {
+            None if !env::var("XDG_CURRENT_DESKTOP")
+                .map_or(false, |desktop| desktop.contains("KDE")) =>
+            {
+                bail!("Not connecting to KWin outside of a KDE session")
+            }
+            None | Some(EnforceWindowManager::Kwin) => {
+                let (tx, rx) = mpsc::channel();
+                let connection = zbus::blocking::ConnectionBuilder::session()?
+                    .serve_at("/", KwinReports(Mutex::new(tx)))?
+                    .build()
+                    .context("Couldn't connect to the session bus")?;
+                let mut wm = Box::new(Self {
+                    connection,
+                    rx,
+                    state: KwinState::default(),
+                    ids: HashMap::new(),
+                });
+                wm.load_script()?;
+                // The script reports as soon as it starts
+                let state = recv(&wm.rx, Some(KWIN_SCRIPT_TIMEOUT))?
+                    .context("KWin script didn't start in time")?;
+                wm.update(&state)?;
+                Ok(wm)
+            }
+            _ => bail!("Not connecting to KWin as we've been explicitly asked not to"),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "KWin"
+    }
+
+    fn get_windows_in_each_workspace(
+        &mut self,
+        config: &Config,
+    ) -> Result<BTreeMap<String, Vec<Window>>> {
+        // Catch up with the reports we haven't waited for
+        if let Some(state) = self.rx.try_iter().last() {
+            self.update(&state)?;
+        }
+        let desktops: Vec<_> = self
+            .state
+            .desktops
+            .iter()
+            .filter(|desktop| !config.ignores_workspace(&desktop.name))
+            .collect();
+        self.ids = desktops
+            .iter()
+            .map(|desktop| (kwin_desktop_name(desktop), desktop.id.clone()))
+            .collect();
+        let mut windows: Vec<_> = self
+            .state
+            .windows
+            .iter()
+            // Windows on all desktops, like docks, don't belong to any of them
+            .filter(|window| !window.on_all_desktops)
+            .collect();
+        // Keep the order of the icons the order of the windows on the screen, from left to right
+        // then top to bottom
+        windows.sort_by(|l, r| {
+            (l.y, l.x)
+                .partial_cmp(&(r.y, r.x))
+                .unwrap_or(Ordering::Equal)
+        });
+        let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        Ok(desktops
+            .into_iter()
+            .map(|desktop| {
+                (
+                    kwin_desktop_name(desktop),
+                    windows
+                        .iter()
+                        .filter(|window| window.desktops.contains(&desktop.id))
+                        .map(|window| Window {
+                            id: Some(WindowId::Uuid(window.id.clone())),
+                            name: non_empty(&window.caption),
+                            app_id: non_empty(&window.desktop_file_name),
+                            window_properties_class: non_empty(&window.resource_class),
+                            window_properties_instance: non_empty(&window.resource_name),
+                            urgent: window.urgent,
+                            focused: window.focused,
+                            fullscreen: window.fullscreen,
+                            // All KWin windows float, so this tells nothing about them
+                            floating: false,
+                            marks: Vec::new(),
+                        })
+                        .filter(|window| window.is_shown(config))
+                        .collect(),
+                )
+            })
+            .collect())
+    }
+
+    fn rename_workspace(&mut self, old: &str, new: &str) -> Result<()> {
+        let id = self
+            .ids
+            .get(old)
+            .with_context(|| format!("No KWin desktop named: {old}"))?;
+        self.call(
+            "/VirtualDesktopManager",
+            "org.kde.KWin.VirtualDesktopManager",
+            "setDesktopName",
+            &(id.as_str(), new),
+        )
+        .with_context(|| format!("Failed to rename workspace from {old} to {new}"))?;
+        Ok(())
+    }
+
+    fn focused_workspace(&mut self) -> Result<Option<String>> {
+        Ok(self
+            .state
+            .desktops
+            .iter()
+            .find(|desktop| desktop.id == self.state.current)
+            .map(kwin_desktop_name))
+    }
+
+    fn wait_for_event(&mut self, config: &Config) -> Result<Change> {
+        if let Some(state) =
+            recv(&self.rx, config.refresh_interval()).context("Failed to wait for event")?
+        {
+            self.update(&state)?;
+        }
+        Ok(Change::Unknown)
+    }
+
+    fn dump_tree(&mut self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(&self.state)?)
+    }
+}
+
+/// D-Bus name of GNOME Shell, which our companion extension is loaded into
+const GNOME_SERVICE: &str = "org.gnome.Shell";
+
+/// Where our companion extension serves the workspaces and windows, see
+/// `gnome-extension/extension.js`
+const GNOME_PATH: &str = "/org/workstyle/Gnome";
+const GNOME_INTERFACE: &str = "org.workstyle.Gnome";
+
+/// A GNOME workspace, as reported by our extension
+#[derive(Debug, Deserialize, Serialize)]
+struct GnomeWorkspace {
+    /// Position of the workspace, starting from 0
+    index: usize,
+    name: String,
+}
+
+/// A Mutter window, as reported by our extension
+#[derive(Debug, Deserialize, Serialize)]
+struct GnomeWindow {
+    id: u64,
+    title: String,
+    app_id: String,
+    wm_class: String,
+    wm_class_instance: String,
+    /// Index of the workspace the window is on, if it is on a single one
+    workspace: Option<usize>,
+    on_all_workspaces: bool,
+    urgent: bool,
+    focused: bool,
+    fullscreen: bool,
+    x: f64,
+    y: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct GnomeState {
+    workspaces: Vec<GnomeWorkspace>,
+    /// Index of the active workspace
+    active: usize,
+    windows: Vec<GnomeWindow>,
+}
+
+fn gnome_workspace_name(workspace: &GnomeWorkspace) -> String {
+    numbered_name(&workspace.name, workspace.index + 1)
+}
+
+pub struct Gnome {
+    proxy: zbus::blocking::Proxy<'static>,
+    /// Receives a message whenever our extension tells us something changed
+    rx: Receiver<()>,
+    /// Index of each workspace by name, as of the last time we got them, since the extension
+    /// names workspaces by index
+    ids: HashMap<String, usize>,
+}
+
+impl Gnome {
+    fn state(&self) -> Result<GnomeState> {
+        let state: String = self
+            .proxy
+            .call("GetState", &())
+            .context("Failed to get the state from our GNOME Shell extension")?;
+        serde_json::from_str(&state).context("Invalid state from our GNOME Shell extension")
+    }
+}
+
+impl WM for Gnome {
+    fn connect(enforce: Option<EnforceWindowManager>, _socket: Option<&Path>) -> Result<Box<Self>> {
+        match enforce {
+            None if !env::var("XDG_CURRENT_DESKTOP")
+                .map_or(false, |desktop| desktop.contains("GNOME")) =>
+            {
+                bail!("Not connecting to GNOME Shell outside of a GNOME session")
+            }
+            None | Some(EnforceWindowManager::Gnome) => {
+                let connection = zbus::blocking::Connection::session()
+                    .context("Couldn't connect to the session bus")?;
+                let proxy = zbus::blocking::Proxy::new(
+                    &connection,
+                    GNOME_SERVICE,
+                    GNOME_PATH,
+                    GNOME_INTERFACE,
+                )?;
+                let changes = proxy.receive_signal("Changed")?;
+                let (tx, rx) = mpsc::channel();
+                thread::spawn(move || {
+                    for _ in changes {
+                        // Sending fails once we've reconnected and dropped the receiver
+                        if tx.send(()).is_err() {
+                            break;
+                        }
+                    }
+                });
+                let wm = Box::new(Self {
+                    proxy,
+                    rx,
+                    ids: HashMap::new(),
+                });
+                wm.state().context(
+                    "Couldn't reach the workstyle GNOME Shell extension. Is it installed and enabled?",
+                )?;
+                Ok(wm)
+            }
+            _ => bail!("Not connecting to GNOME Shell as we've been explicitly asked not to"),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "GNOME Shell"
+    }
+
+    fn get_windows_in_each_workspace(
+        &mut self,
+        config: &Config,
+    ) -> Result<BTreeMap<String, Vec<Window>>> {
+        let state = self.state()?;
+        let workspaces: Vec<_> = state
+            .workspaces
+            .iter()
+            .filter(|workspace| !config.ignores_workspace(&workspace.name))
+            .collect();
+        self.ids = workspaces
+            .iter()
+            .map(|workspace| (gnome_workspace_name(workspace), workspace.index))
+            .collect();
+        let mut windows: Vec<_> = state
+            .windows
+            .iter()
+            .filter(|window| !window.on_all_workspaces)
+            .collect();
+        // Keep the order of the icons the order of the windows on the screen, from left to right
+        // then top to bottom
+        windows.sort_by(|l, r| {
+            (l.y, l.x)
+                .partial_cmp(&(r.y, r.x))
+                .unwrap_or(Ordering::Equal)
+        });
+        let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        Ok(workspaces
+            .into_iter()
+            .map(|workspace| {
+                (
+                    gnome_workspace_name(workspace),
+                    windows
+                        .iter()
+                        .filter(|window| window.workspace == Some(workspace.index))
+                        .map(|window| Window {
+                            id: Some(WindowId::Mutter(window.id)),
+                            name: non_empty(&window.title),
+                            app_id: non_empty(&window.app_id),
+                            window_properties_class: non_empty(&window.wm_class),
+                            window_properties_instance: non_empty(&window.wm_class_instance),
+                            urgent: window.urgent,
+                            focused: window.focused,
+                            fullscreen: window.fullscreen,
+                            // GNOME doesn't tile, so this tells nothing about the windows
+                            floating: false,
+                            marks: Vec::new(),
+                        })
+                        .filter(|window| window.is_shown(config))
+                        .collect(),
+                )
+            })
+            .collect())
+    }
+
+    fn rename_workspace(&mut self, old: &str, new: &str) -> Result<()> {
+        let index = *self
+            .ids
+            .get(old)
+            .with_context(|| format!("No GNOME workspace named: {old}"))?;
+        self.proxy
+            .call::<_, _, ()>("SetName", &(index as u32, new))
+            .with_context(|| format!("Failed to rename workspace from {old} to {new}"))?;
+        Ok(())
+    }
+
+    fn focused_workspace(&mut self) -> Result<Option<String>> {
+        let state = self.state()?;
+        Ok(state
+            .workspaces
+            .iter()
+            .find(|workspace| workspace.index == state.active)
+            .map(gnome_workspace_name))
     }
 
-    fn wait_for_event(&mut self) -> Result<()> {
-        self.rx.recv().context("Failed to wait for event")
+    fn wait_for_event(&mut self, config: &Config) -> Result<Change> {
+        recv(&self.rx, config.refresh_interval()).context("Failed to wait for event")?;
+        // A single change is often signalled several times, e.g. a new window also takes the
+        // focus
+        self.rx.try_iter().for_each(drop);
+        Ok(Change::Unknown)
+    }
+
+    fn dump_tree(&mut self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(self.state()?)?)
+    }
+}
+
+/// The sway/i3 command renaming the workspace, with the names quoted so they can contain any
+/// character
+pub(crate) fn rename_command(old: &str, new: &str) -> String {
+    let quote = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+    format!("rename workspace {} to {}", quote(old), quote(new))
+}
+
+/// What changed according to a sway/i3 event, or why we didn't get any
+fn sway_change(event: Option<Fallible<Event>>) -> Result<Change> {
+    match event {
+        Some(Err(e)) => Err(anyhow!(e).context("Failed to receive next event")),
+        Some(Ok(Event::Window(event))) => Ok(match event.change {
+            // These only affect the workspace the window is in. Others may also affect the
+            // workspace it was in or the window that was focused before
+            WindowChange::New
+            | WindowChange::Title
+            | WindowChange::Urgent
+            | WindowChange::Floating
+            | WindowChange::FullscreenMode
+            | WindowChange::Mark => Change::Window(WindowId::Node(event.container.id)),
+            _ => Change::Unknown,
+        }),
+        _ => Ok(Change::Unknown),
+    }
+}
+
+/// Whether a sway/i3 workspace event may change the names. Only the empty workspaces depend on
+/// these: new ones get the `empty_icon`, and the focused one the `focused_empty_icon`. Other
+/// workspace events, like our own renames, don't change any name
+fn workspace_event_matters(config: &Config, event: &WorkspaceEvent) -> bool {
+    match event.change {
+        WorkspaceChange::Init | WorkspaceChange::Focus if config.has_empty_icon() => true,
+        WorkspaceChange::Focus => config.other.focused_empty_icon.is_some(),
+        _ => false,
     }
 }
 
 pub struct SwayOrI3 {
     connection: Connection,
-    events: EventStream,
+    events: Receiver<Fallible<Event>>,
+    /// Whether we're connected to sway rather than i3
+    sway: bool,
 }
 
 impl WM for SwayOrI3 {
-    fn connect(enforce: Option<EnforceWindowManager>) -> Result<Box<Self>> {
+    fn connect(enforce: Option<EnforceWindowManager>, socket: Option<&Path>) -> Result<Box<Self>> {
         match enforce {
-            None | Some(EnforceWindowManager::SwayOrI3) => Ok(Box::new(Self {
-                connection: Connection::new().context("Couldn't connect to WM")?,
-                events: Connection::new()
-                    .context("Couldn't connect to WM")?
-                    .subscribe([EventType::Window])
-                    .context("Couldn't subscribe to events of type Window")?,
-            })),
+            None | Some(EnforceWindowManager::SwayOrI3) => {
+                let new_connection = || match socket {
+                    Some(socket) => UnixStream::connect(socket)
+                        .map(Connection::from)
+                        .with_context(|| format!("Couldn't connect to WM socket: {socket:?}")),
+                    None => Connection::new().context("Couldn't connect to WM"),
+                };
+                let mut connection = new_connection()?;
+                let version = connection
+                    .get_version()
+                    .context("Couldn't get the version of the WM")?;
+                // Both speak the same protocol, but i3 has been at version 4 for over a decade,
+                // while sway is at version 1
+                let sway = version.major < 4;
+                debug!(
+                    "Connected to {} version: {}",
+                    if sway { "sway" } else { "i3" },
+                    version.human_readable
+                );
+                let event_stream = new_connection()?
+                    .subscribe([EventType::Window, EventType::Workspace])
+                    .context("Couldn't subscribe to events of type Window and Workspace")?;
+                // Forward the events to a channel, so we can wait for them with a timeout
+                let (tx, events) = mpsc::channel();
+                thread::spawn(move || {
+                    for event in event_stream {
+                        if tx.send(event).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Ok(Box::new(Self {
+                    connection,
+                    events,
+                    sway,
+                }))
+            }
             _ => bail!("Not connecting to Sway or i3 as we've explicitly been asked not to"),
         }
     }
 
-    fn get_windows_in_each_workspace(&mut self) -> Result<BTreeMap<String, Vec<Window>>> {
+    fn name(&self) -> &'static str {
+        if self.sway {
+            "Sway"
+        } else {
+            "i3"
+        }
+    }
+
+    fn get_windows_in_each_workspace(
+        &mut self,
+        config: &Config,
+    ) -> Result<BTreeMap<String, Vec<Window>>> {
         self.connection
             .get_tree()
             .context("get_tree() failed")?
-            .workspaces_in_node()
+            .workspaces_in_node(config)
     }
 
     fn rename_workspace(&mut self, old: &str, new: &str) -> Result<()> {
@@ -311,19 +1276,67 @@ impl WM for SwayOrI3 {
         }
         for result in self
             .connection
-            .run_command(&format!("rename workspace \"{old}\" to \"{new}\"",))
+            .run_command(&rename_command(old, new))
             .context("Failed to rename the workspace")?
         {
-            result.context("Failed to rename the workspace")?;
+            result.context(format!("Failed to rename workspace from {old} to {new}"))?;
         }
         Ok(())
     }
 
-    fn wait_for_event(&mut self) -> Result<()> {
-        match self.events.next() {
-            Some(Err(e)) => Err(anyhow!(e).context("Failed to receive next event")),
-            None => bail!("Event stream ended"),
-            _ => Ok(()),
+    /// Send all the renames in a single command, to save round trips to the WM
+    fn rename_workspaces(&mut self, renames: &[(String, String)]) -> Result<()> {
+        let renames: Vec<_> = renames.iter().filter(|(old, new)| old != new).collect();
+        if renames.is_empty() {
+            return Ok(());
         }
+        let command = renames
+            .iter()
+            .map(|(old, new)| rename_command(old, new))
+            .join("; ");
+        let results = self
+            .connection
+            .run_command(&command)
+            .context("Failed to rename the workspaces")?;
+        // There is one result per command, in order
+        for ((old, new), result) in renames.iter().zip(results) {
+            if let Err(e) = result {
+                warn!("Failed to rename workspace from {old} to {new}: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    fn focused_workspace(&mut self) -> Result<Option<String>> {
+        Ok(self
+            .connection
+            .get_workspaces()
+            .context("Failed to get workspaces")?
+            .into_iter()
+            .find(|workspace| workspace.focused)
+            .map(|workspace| workspace.name))
+    }
+
+    fn wait_for_event(&mut self, config: &Config) -> Result<Change> {
+        let deadline = config
+            .refresh_interval()
+            .map(|interval| Instant::now() + interval);
+        loop {
+            let timeout =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            match recv(&self.events, timeout)? {
+                Some(Ok(Event::Workspace(event))) if !workspace_event_matters(config, &event) => {
+                    continue
+                }
+                event => return sway_change(event),
+            }
+        }
+    }
+    fn dump_tree(&mut self) -> Result<serde_json::Value> {
+        Ok(self
+            .connection
+            .get_tree()
+            .context("get_tree() failed")?
+            .to_json())
     }
 }